@@ -0,0 +1,42 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use i3_window_killer::desktop_index::DesktopIndex;
+use std::{fs, path::PathBuf};
+
+const ENTRIES: usize = 2000;
+
+fn create_applications_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("i3-window-killer-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("couldn't create bench directory");
+    for i in 0..ENTRIES {
+        fs::write(
+            dir.join(format!("org.example.App{}.desktop", i)),
+            format!(
+                "[Desktop Entry]\nType=Application\nName=Example Application {}\nGenericName=Example\nComment=Synthetic entry\nExec=example-app-{} %U\nIcon=example-app-{}\nCategories=Utility;\n",
+                i, i, i
+            ),
+        )
+        .expect("couldn't write desktop file");
+    }
+    dir
+}
+
+fn bench_desktop_index(c: &mut Criterion) {
+    let dir = create_applications_dir();
+    c.bench_function("build 2000 entries", |b| {
+        b.iter(|| DesktopIndex::build(&[&dir]))
+    });
+    let index = DesktopIndex::build(&[&dir]);
+    let classes = ["Example Application 1999", "App42", "Firefox"];
+    c.bench_function("lookup 3 classes", |b| {
+        b.iter(|| {
+            classes
+                .iter()
+                .filter_map(|class| index.find_by_class(black_box(class)))
+                .count()
+        })
+    });
+    fs::remove_dir_all(&dir).expect("couldn't remove bench directory");
+}
+
+criterion_group!(benches, bench_desktop_index);
+criterion_main!(benches);
@@ -1,19 +1,74 @@
-use crate::utils::fs::{dir_exists, file_exists, get_default_icon_cache};
-use clap::{crate_version, App, Arg};
+use crate::{
+    settings::Settings,
+    templates::TEMPLATES,
+    utils::fs::{
+        dir_exists, file_exists, get_applications_dirs, get_default_config_dir,
+        get_default_icon_cache, get_default_runtime_dir, get_default_state_dir,
+    },
+};
+use clap::{crate_version, App, AppSettings, Arg, SubCommand};
 use std::{path::PathBuf, str::FromStr};
 
 const APP_NAME: &str = "i3-window-killer";
 const ICONS_CACHE_FILENAME: &str = "icons";
+const HISTORY_FILENAME: &str = "history";
+const LAYOUTS_DIRNAME: &str = "layouts";
+const SETTINGS_FILENAME: &str = "config.toml";
 
 const ARG_GLOBAL_OUTER_GAP: &str = "global_outer_gaps";
 const ARG_SMART_GAPS: &str = "smart_gaps";
 const ARG_ROFI_CONFIG: &str = "rofi_config";
 const ARG_ROFI_THEME_FILE: &str = "rofi_theme_file";
+const ARG_TEMPLATE_NAME: &str = "template_name";
+const ARG_LIST_TEMPLATES: &str = "list_templates";
 const ARG_DUMP_STYLES: &str = "dump_styles";
 const ARG_NO_CACHE: &str = "no_cache";
 const ARG_CACHE_DIR: &str = "cache_dir";
+const ARG_APPLICATIONS_DIR: &str = "applications_dir";
+const ARG_SETTINGS: &str = "settings";
+const ARG_REOPEN_INDEX: &str = "reopen_index";
+const ARG_CLASS: &str = "class";
+const ARG_ICON: &str = "icon";
+const ARG_INSTANCE: &str = "instance";
+
+const CMD_HISTORY: &str = "history";
+const CMD_REOPEN: &str = "reopen";
+const CMD_UNDO: &str = "undo";
+const CMD_DAEMON: &str = "daemon";
+const CMD_CLIENT: &str = "client";
+const CMD_EXPLAIN_ICON: &str = "explain-icon";
+const CMD_CACHE: &str = "cache";
+const CMD_CACHE_LIST: &str = "list";
+const CMD_CACHE_GET: &str = "get";
+const CMD_CACHE_SET: &str = "set";
+const CMD_CACHE_REMOVE: &str = "remove";
+const CMD_CACHE_PRUNE: &str = "prune";
+const CMD_CACHE_REBUILD: &str = "rebuild";
+
+#[derive(Debug)]
+pub enum Command {
+    Kill,
+    History,
+    Reopen(usize),
+    Undo,
+    Daemon,
+    Client,
+    ExplainIcon(String, Option<String>),
+    Cache(CacheCommand),
+    ListTemplates,
+}
 
 #[derive(Debug)]
+pub enum CacheCommand {
+    List,
+    Get(String),
+    Set(String, String),
+    Remove(String),
+    Prune,
+    Rebuild,
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum SmartGapsOption {
     Off,
     On,
@@ -34,17 +89,26 @@ impl FromStr for SmartGapsOption {
 
 #[derive(Debug)]
 pub struct Options {
+    pub command: Command,
     pub global_outer_gap: Option<i32>,
     pub global_smart_gaps: SmartGapsOption,
     pub rofi_config: Option<String>,
     pub rofi_theme_file: Option<PathBuf>,
+    /// Embedded template, used when no template file is given.
+    pub template_name: Option<String>,
     pub dump_styles: bool,
     pub no_cache: bool,
     pub cache_file_path: Option<PathBuf>,
+    pub history_file_path: Option<PathBuf>,
+    pub layouts_dir: Option<PathBuf>,
+    pub socket_path: Option<PathBuf>,
+    pub applications_dirs: Vec<PathBuf>,
+    pub settings: Settings,
 }
 
 pub fn get_options() -> Options {
     let default_cache = get_default_icon_cache().unwrap_or_default();
+    let template_names: Vec<&str> = TEMPLATES.iter().map(|template| template.name).collect();
     let matches = App::new(APP_NAME)
         .version(crate_version!())
         .about("Show rofi confirmation prompt before killing the focused i3wm node")
@@ -67,22 +131,125 @@ Template engine syntax: https://docs.rs/tinytemplate/1.2.1/tinytemplate/syntax/i
 Interpolated variables:
     - container // Object of the focused node (access props with { container.prop })
         {
-            top: Integer, // top value of the container rect in pixels
+            top: Integer, // top value of the container rect in logical pixels
             right: Integer, 
             bottom: Integer,
             left: Integer,
+            width: Integer,
+            height: Integer,
+            center_x: Integer,
+            center_y: Integer,
+            output_percent: { top: Float, right: Float, bottom: Float, left: Float, width: Float, height: Float }, // relative to the output
+            fits: Boolean, // whether the minimum prompt size fits inside the container
+            placement: { top, right, bottom, left, width, height, center_x, center_y }, // the container if it fits, else the minimum prompt size centered on the output
+            physical: { top, right, bottom, left, width, height, center_x, center_y }, // the container in physical pixels
+            scale: Float, // scale factor of the output of the container
         }
-    - nodes // Array of windows within the container node
+    - nodes // Array of windows within the container node (or groups, with `group_by_class`)
         [
             { // Object containing node props
+                con_id: Integer, // i3 container id
+                window: Integer, // X11 window id
                 class: String, // window X11 class
+                instance: String, // window X11 instance
                 title: String, // window X11 title
+                window_role: String, // window X11 role
+                window_type: String, // e.g. normal, dialog, utility
                 icon: String, // window desktop icon
+                icon_path: String, // file of the icon in the icon theme (empty if not found)
+                app: { // localized keys of the matching desktop entry (empty strings if none)
+                    name: String,
+                    generic_name: String,
+                    comment: String,
+                    exec: String,
+                },
+                marks: [String], // i3 marks
+                urgent: Boolean,
+                focused: Boolean,
+                floating: Boolean,
+                fullscreen: Boolean,
+                sticky: Boolean,
+                scratchpad: Boolean, // hidden in the scratchpad
+                workspace: String, // workspace name
+                workspace_num: Integer, // workspace number (if any)
+                output: String, // output name
+                rect: { x: Integer, y: Integer, width: Integer, height: Integer }, // absolute geometry in pixels
+                deco_rect: { x: Integer, y: Integer, width: Integer, height: Integer }, // title bar, relative to the parent
             }
-        ]"##)
+        ]
+    - groups // Array of the windows grouped by class, in order of appearance
+        [
+            {
+                class: String,
+                title: String, // title of the window if alone, else "<count> windows"
+                icon: String,
+                icon_path: String,
+                count: Integer,
+                titles: [String],
+            }
+        ]
+    - tree // Object mirroring the container node and its descendants
+        {
+            con_id: Integer, // i3 container id
+            node_type: String, // con, floating_con, workspace...
+            layout: String, // splith, splitv, stacked, tabbed...
+            orientation: String, // horizontal, vertical or none
+            percent: Float, // share of the parent (if any)
+            window: Object, // props of the window, as in nodes (if any)
+            children: [Object], // tiling children, shaped like tree
+            floating_children: [Object], // floating children, shaped like tree
+        }
+    - minimap // Object describing every window of the workspace (absent if the container is not in one)
+        {
+            workspace: String, // workspace name
+            width: Integer, // workspace size in pixels
+            height: Integer,
+            windows: [
+                {
+                    con_id: Integer,
+                    class: String,
+                    title: String,
+                    floating: Boolean,
+                    focused: Boolean,
+                    targeted: Boolean, // whether the window is about to be killed
+                    x: Float, // geometry in percentages of the workspace
+                    y: Float,
+                    width: Float,
+                    height: Float,
+                }
+            ]
+        }
+    - i3 // Object of the theme values of the i3 config (included files aren't read)
+        {
+            colors: {
+                focused: { border: String, background: String, text: String, indicator: String, child_border: String }, // client.focused
+                focused_inactive, focused_tab_title, unfocused, urgent, placeholder: { ... }, // client.<class>, when set
+                background: String, // client.background
+            },
+            font: String, // font of the title bars, without its "pango:" prefix
+            vars: { <name>: String }, // set $<name> <value> variables
+        }
+    - palette // Object of the colors of the palette sources in the settings: { color0: String, ..., background: String, foreground: String }"##)
                 .takes_value(true)
                 .validator(file_exists),
         )
+        .arg(
+            Arg::with_name(ARG_TEMPLATE_NAME)
+                .value_name("NAME")
+                .long("template-name")
+                .long_help(
+r##"Embedded template to render the styles with, instead of a template file.
+It includes the rofi configuration it needs, see --list-templates"##)
+                .takes_value(true)
+                .possible_values(&template_names)
+                .hide_possible_values(true)
+                .conflicts_with(ARG_ROFI_THEME_FILE),
+        )
+        .arg(
+            Arg::with_name(ARG_LIST_TEMPLATES)
+                .long("list-templates")
+                .long_help("List the embedded templates and exit")
+        )
         .arg(
             Arg::with_name(ARG_GLOBAL_OUTER_GAP)
                 .value_name("INTEGER")
@@ -129,9 +296,170 @@ If unspecified, $XDG_CACHE_HOME or $HOME/.cache will be used"##, APP_NAME).as_st
                 .validator(dir_exists)
                 .default_value(default_cache.as_str()),
         )
+        .arg(
+            Arg::with_name(ARG_APPLICATIONS_DIR)
+                .value_name("DIR")
+                .long("applications-dir")
+                .long_help(
+r##"Additional directory of desktop entries, searched before the XDG ones (can be repeated).
+$XDG_DATA_HOME/applications, $XDG_DATA_DIRS/applications, Flatpak and Snap exports are always searched"##)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(dir_exists),
+        )
+        .arg(
+            Arg::with_name(ARG_SETTINGS)
+                .value_name("FILE")
+                .long("settings")
+                .long_help(format!(
+r##"Settings file (TOML), see the README for its keys.
+If unspecified, $XDG_CONFIG_HOME/{0}/{1} or $HOME/.config/{0}/{1} is read when it exists"##, APP_NAME, SETTINGS_FILENAME).as_str())
+                .takes_value(true)
+                .validator(file_exists),
+        )
+        .subcommand(
+            SubCommand::with_name(CMD_HISTORY)
+                .about("List recently killed windows, most recent first"),
+        )
+        .subcommand(
+            SubCommand::with_name(CMD_REOPEN)
+                .about("Relaunch a recently killed window on its original workspace")
+                .arg(
+                    Arg::with_name(ARG_REOPEN_INDEX)
+                        .value_name("N")
+                        .long_help("Index of the entry in the history listing")
+                        .default_value("1")
+                        .validator(|s| match s.parse::<usize>() {
+                            Ok(n) if n > 0 => Ok(()),
+                            _ => Err(format!("{} is not a positive integer", s)),
+                        }),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(CMD_UNDO)
+                .about("Recreate the layout of the last kill and relaunch its windows into it"),
+        )
+        .subcommand(
+            SubCommand::with_name(CMD_DAEMON).about(
+                "Stay in the background with a warm i3 connection and icon index, serving kill requests from `client`",
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name(CMD_CLIENT)
+                .about("Ask the running daemon to prompt for the focused node (runs in-process if there is none)"),
+        )
+        .subcommand(
+            SubCommand::with_name(CMD_EXPLAIN_ICON)
+                .about("Show how the icon of a window class is resolved, with every candidate desktop entry")
+                .arg(Arg::with_name(ARG_CLASS).value_name("CLASS").required(true))
+                .arg(
+                    Arg::with_name(ARG_INSTANCE)
+                        .value_name("INSTANCE")
+                        .long("instance")
+                        .long_help("X11 instance of the window")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(CMD_CACHE)
+                .about("Inspect and edit the icons cache")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name(CMD_CACHE_LIST).about("List the cached icons"))
+                .subcommand(
+                    SubCommand::with_name(CMD_CACHE_GET)
+                        .about("Show the cached icons of a window class")
+                        .arg(Arg::with_name(ARG_CLASS).value_name("CLASS").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name(CMD_CACHE_SET)
+                        .about("Force the icon of a window class, replacing its cached icons")
+                        .arg(Arg::with_name(ARG_CLASS).value_name("CLASS").required(true))
+                        .arg(Arg::with_name(ARG_ICON).value_name("ICON").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name(CMD_CACHE_REMOVE)
+                        .about("Forget the cached icons of a window class")
+                        .arg(Arg::with_name(ARG_CLASS).value_name("CLASS").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name(CMD_CACHE_PRUNE)
+                        .about("Drop the icons whose desktop file vanished or changed, and the expired failed lookups"),
+                )
+                .subcommand(
+                    SubCommand::with_name(CMD_CACHE_REBUILD)
+                        .about("Resolve again the icons of every window currently open"),
+                ),
+        )
         .get_matches();
+    let state_dir = get_default_state_dir().map(|dir| dir.join(APP_NAME));
+    let settings_file_path = matches
+        .value_of(ARG_SETTINGS)
+        .map(PathBuf::from)
+        .or_else(|| {
+            get_default_config_dir()
+                .map(|dir| dir.join(APP_NAME).join(SETTINGS_FILENAME))
+                .filter(|path| path.is_file())
+        });
 
     Options {
+        command: match matches.subcommand() {
+            (CMD_HISTORY, _) => Command::History,
+            (CMD_REOPEN, Some(sub_matches)) => Command::Reopen(
+                sub_matches
+                    .value_of(ARG_REOPEN_INDEX)
+                    .map(|s| s.parse::<usize>().expect("couldn't parse reopen index"))
+                    .expect("couldn't get reopen index"),
+            ),
+            (CMD_UNDO, _) => Command::Undo,
+            (CMD_DAEMON, _) => Command::Daemon,
+            (CMD_CLIENT, _) => Command::Client,
+            (CMD_EXPLAIN_ICON, Some(sub_matches)) => Command::ExplainIcon(
+                sub_matches
+                    .value_of(ARG_CLASS)
+                    .map(|s| s.to_string())
+                    .expect("couldn't get class"),
+                sub_matches.value_of(ARG_INSTANCE).map(|s| s.to_string()),
+            ),
+            (CMD_CACHE, Some(sub_matches)) => {
+                let get_arg = |matches: Option<&clap::ArgMatches>, name| {
+                    matches
+                        .and_then(|matches| matches.value_of(name))
+                        .map(|s| s.to_string())
+                        .expect("couldn't get cache argument")
+                };
+                Command::Cache(match sub_matches.subcommand() {
+                    (CMD_CACHE_GET, matches) => CacheCommand::Get(get_arg(matches, ARG_CLASS)),
+                    (CMD_CACHE_SET, matches) => {
+                        CacheCommand::Set(get_arg(matches, ARG_CLASS), get_arg(matches, ARG_ICON))
+                    }
+                    (CMD_CACHE_REMOVE, matches) => {
+                        CacheCommand::Remove(get_arg(matches, ARG_CLASS))
+                    }
+                    (CMD_CACHE_PRUNE, _) => CacheCommand::Prune,
+                    (CMD_CACHE_REBUILD, _) => CacheCommand::Rebuild,
+                    _ => CacheCommand::List,
+                })
+            }
+            _ if matches.is_present(ARG_LIST_TEMPLATES) => Command::ListTemplates,
+            _ => Command::Kill,
+        },
+        history_file_path: state_dir.as_ref().map(|dir| dir.join(HISTORY_FILENAME)),
+        layouts_dir: state_dir.map(|dir| dir.join(LAYOUTS_DIRNAME)),
+        applications_dirs: get_applications_dirs(
+            &matches
+                .values_of(ARG_APPLICATIONS_DIR)
+                .map(|values| values.map(PathBuf::from).collect::<Vec<PathBuf>>())
+                .unwrap_or_default(),
+        ),
+        settings: settings_file_path
+            .map(|path| {
+                Settings::read(&path).unwrap_or_else(|error| {
+                    panic!("couldn't read settings file {}: {}", path.display(), error)
+                })
+            })
+            .unwrap_or_default(),
+        socket_path: get_default_runtime_dir().map(|dir| dir.join(format!("{}.sock", APP_NAME))),
         no_cache: matches.is_present(ARG_NO_CACHE),
         cache_file_path: matches
             .value_of(ARG_CACHE_DIR)
@@ -158,5 +486,6 @@ If unspecified, $XDG_CACHE_HOME or $HOME/.cache will be used"##, APP_NAME).as_st
         rofi_theme_file: matches
             .value_of(ARG_ROFI_THEME_FILE)
             .map(|s| PathBuf::from(s)),
+        template_name: matches.value_of(ARG_TEMPLATE_NAME).map(|s| s.to_string()),
     }
 }
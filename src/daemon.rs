@@ -0,0 +1,184 @@
+use crate::{
+    cli::Options,
+    desktop_index::DesktopIndex,
+    external_command::{get_tree, is_sway},
+    formatter::IconState,
+    session::kill_focused,
+};
+use i3_ipc::{Connect, I3};
+use inotify::{Inotify, WatchMask};
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+pub const REQUEST_KILL: &str = "kill";
+const RESPONSE_OK: &str = "ok";
+const RESPONSE_ERROR: &str = "error";
+
+/// Watches the directory and its subdirectories, which are scanned for desktop
+/// files too.
+fn add_watches(inotify: &mut Inotify, dir: &Path) -> io::Result<()> {
+    inotify.add_watch(
+        dir,
+        WatchMask::CREATE
+            | WatchMask::DELETE
+            | WatchMask::MODIFY
+            | WatchMask::MOVED_FROM
+            | WatchMask::MOVED_TO,
+    )?;
+    for entry in fs::read_dir(dir)?.filter_map(|entry| entry.ok()) {
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            add_watches(inotify, &entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Rescans the desktop files whenever an applications directory changes and drops
+/// the icons resolved so far, along with the cached ones the rescan may change,
+/// since a better match may have appeared.
+fn watch_desktop_files(
+    icon_state: Arc<Mutex<IconState>>,
+    applications_dirs: Vec<PathBuf>,
+) -> io::Result<()> {
+    const DEBOUNCE: Duration = Duration::from_millis(250);
+    let mut inotify = Inotify::init()?;
+    for dir in applications_dirs.iter() {
+        add_watches(&mut inotify, dir)?;
+    }
+    let mut buffer = [0; 4096];
+    loop {
+        inotify.read_events_blocking(&mut buffer)?;
+        // package managers touch many files at once, wait for the burst to end
+        thread::sleep(DEBOUNCE);
+        while let Ok(mut events) = inotify.read_events(&mut buffer) {
+            if events.next().is_none() {
+                break;
+            }
+        }
+        // watch the subdirectories created since
+        for dir in applications_dirs.iter() {
+            if let Err(error) = add_watches(&mut inotify, dir) {
+                eprintln!("Couldn't watch {}: {}", dir.display(), error);
+            }
+        }
+        let desktop_index = DesktopIndex::build(&applications_dirs);
+        let indexed: HashSet<PathBuf> = desktop_index
+            .entries()
+            .iter()
+            .map(|entry| entry.path.clone())
+            .collect();
+        let mut icon_state = icon_state.lock().unwrap_or_else(|e| e.into_inner());
+        icon_state.desktop_index = Some(desktop_index);
+        icon_state.icon_map.clear();
+        if let Some(cache) = icon_state.cache.as_mut() {
+            cache.refresh();
+            cache.invalidate(|path| indexed.contains(path));
+            if let Err(error) = cache.save() {
+                eprintln!("Couldn't save icon cache: {}", error);
+            }
+        }
+    }
+}
+
+pub fn run(options: Options) -> io::Result<()> {
+    let socket_path = options.socket_path.clone().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "couldn't locate socket ($XDG_RUNTIME_DIR is unset)",
+        )
+    })?;
+    if socket_path.exists() {
+        if UnixStream::connect(&socket_path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "daemon is already running",
+            ));
+        }
+        fs::remove_file(&socket_path)?;
+    }
+    let listener = UnixListener::bind(&socket_path)?;
+
+    let mut icon_state = IconState::new(&options);
+    icon_state.desktop_index();
+    icon_state.icon_theme();
+    let icon_state = Arc::new(Mutex::new(icon_state));
+    {
+        let icon_state = Arc::clone(&icon_state);
+        let applications_dirs = options.applications_dirs.clone();
+        thread::spawn(move || {
+            if let Err(error) = watch_desktop_files(icon_state, applications_dirs) {
+                eprintln!("Stopped watching desktop files: {}", error);
+            }
+        });
+    }
+
+    let sway = is_sway();
+    let mut con = I3::connect()?;
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("Couldn't accept client: {}", error);
+                continue;
+            }
+        };
+        let mut request = String::new();
+        if let Err(error) = BufReader::new(&stream).read_line(&mut request) {
+            eprintln!("Couldn't read request: {}", error);
+            continue;
+        }
+        // a panicking request mustn't take the daemon and its socket down
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| match request.trim() {
+            REQUEST_KILL => {
+                // the connection is dropped when i3 restarts, reconnect once
+                let tree = match get_tree(&mut con) {
+                    Ok(tree) => Ok(tree),
+                    Err(_) => I3::connect().and_then(|new_con| {
+                        con = new_con;
+                        get_tree(&mut con)
+                    }),
+                };
+                tree.and_then(|tree| kill_focused(&mut con, &tree, &options, &icon_state, sway))
+            }
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown request: {}", other),
+            )),
+        }))
+        .unwrap_or_else(|_| Err(io::Error::other("request panicked")));
+        let response = match outcome {
+            Ok(()) => RESPONSE_OK.to_string(),
+            Err(error) => format!("{}: {}", RESPONSE_ERROR, error),
+        };
+        if let Err(error) = writeln!(stream, "{}", response) {
+            eprintln!("Couldn't answer client: {}", error);
+        }
+    }
+    Ok(())
+}
+
+/// Sends a request to the daemon and waits for it to be handled, the outer error
+/// meaning the daemon couldn't be reached.
+pub fn send_request(socket_path: &Path, request: &str) -> io::Result<Result<(), String>> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    writeln!(stream, "{}", request)?;
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    Ok(match response.trim() {
+        RESPONSE_OK => Ok(()),
+        response => Err(response
+            .strip_prefix(RESPONSE_ERROR)
+            .map(|error| error.trim_start_matches(": "))
+            .unwrap_or(response)
+            .to_string()),
+    })
+}
@@ -0,0 +1,354 @@
+//! Parser for the freedesktop [Desktop Entry specification](https://specifications.freedesktop.org/desktop-entry-spec/desktop-entry-spec-latest.html).
+
+use std::{env, fs, io, path::Path};
+
+pub const MAIN_GROUP: &str = "Desktop Entry";
+
+/// A `lang_COUNTRY.ENCODING@MODIFIER` locale, as found in `LC_MESSAGES`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Locale {
+    pub lang: String,
+    pub country: Option<String>,
+    pub modifier: Option<String>,
+}
+
+impl Locale {
+    pub fn parse(s: &str) -> Option<Self> {
+        let (s, modifier) = match s.split_once('@') {
+            Some((s, modifier)) => (s, Some(modifier.to_owned())),
+            None => (s, None),
+        };
+        let s = s.split('.').next().unwrap_or(s);
+        let (lang, country) = match s.split_once('_') {
+            Some((lang, country)) => (lang, Some(country.to_owned())),
+            None => (s, None),
+        };
+        if lang.is_empty() || lang == "C" || lang == "POSIX" {
+            None
+        } else {
+            Some(Locale {
+                lang: lang.to_owned(),
+                country,
+                modifier,
+            })
+        }
+    }
+
+    /// Reads the messages locale from `LC_ALL`, `LC_MESSAGES` then `LANG`.
+    pub fn from_env() -> Option<Self> {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Locale::parse(&value))
+    }
+
+    /// Localized key suffixes to try, most specific first.
+    fn candidates(&self) -> Vec<String> {
+        let mut candidates = Vec::new();
+        if let (Some(country), Some(modifier)) = (&self.country, &self.modifier) {
+            candidates.push(format!("{}_{}@{}", self.lang, country, modifier));
+        }
+        if let Some(country) = &self.country {
+            candidates.push(format!("{}_{}", self.lang, country));
+        }
+        if let Some(modifier) = &self.modifier {
+            candidates.push(format!("{}@{}", self.lang, modifier));
+        }
+        candidates.push(self.lang.clone());
+        candidates
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Group {
+    pub name: String,
+    /// Raw (still escaped) values, keyed by `Key` or `Key[locale]`.
+    entries: Vec<(String, String)>,
+}
+
+impl Group {
+    pub fn get_raw(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.get_raw(key).map(unescape)
+    }
+
+    pub fn get_localized(&self, key: &str, locale: Option<&Locale>) -> Option<String> {
+        locale
+            .into_iter()
+            .flat_map(|locale| locale.candidates())
+            .find_map(|suffix| self.get(&format!("{}[{}]", key, suffix)))
+            .or_else(|| self.get(key))
+    }
+
+    pub fn get_bool(&self, key: &str) -> bool {
+        self.get_raw(key).is_some_and(|v| v.trim() == "true")
+    }
+
+    pub fn get_list(&self, key: &str) -> Vec<String> {
+        self.get_raw(key).map(split_list).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DesktopEntry {
+    groups: Vec<Group>,
+}
+
+impl DesktopEntry {
+    /// Parses the entry leniently: malformed lines, keys before the first group and
+    /// duplicated keys (after the first occurrence) are ignored.
+    pub fn parse(text: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        for line in text.lines() {
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                if let Some(name) = name.trim_end().strip_suffix(']') {
+                    groups.push(Group {
+                        name: name.to_owned(),
+                        entries: Vec::new(),
+                    });
+                }
+                continue;
+            }
+            if let (Some(group), Some((key, value))) = (groups.last_mut(), line.split_once('=')) {
+                let key = key.trim_end();
+                if is_valid_key(key) && group.get_raw(key).is_none() {
+                    group
+                        .entries
+                        .push((key.to_owned(), value.trim_start().to_owned()));
+                }
+            }
+        }
+        DesktopEntry { groups }
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        fs::read_to_string(path).map(|text| DesktopEntry::parse(&text))
+    }
+
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+
+    pub fn groups(&self) -> &[Group] {
+        &self.groups
+    }
+
+    fn main_group(&self) -> Option<&Group> {
+        self.group(MAIN_GROUP)
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.main_group().and_then(|group| group.get(key))
+    }
+
+    pub fn get_localized(&self, key: &str, locale: Option<&Locale>) -> Option<String> {
+        self.main_group()
+            .and_then(|group| group.get_localized(key, locale))
+    }
+
+    pub fn get_bool(&self, key: &str) -> bool {
+        self.main_group().is_some_and(|group| group.get_bool(key))
+    }
+
+    pub fn entry_type(&self) -> Option<String> {
+        self.get("Type")
+    }
+
+    pub fn name(&self) -> Option<String> {
+        self.get("Name")
+    }
+
+    pub fn icon(&self) -> Option<String> {
+        self.get("Icon").map(|icon| icon.trim().to_owned())
+    }
+
+    pub fn exec(&self) -> Option<String> {
+        self.get("Exec")
+    }
+
+    pub fn startup_wm_class(&self) -> Option<String> {
+        self.get("StartupWMClass")
+    }
+
+    pub fn hidden(&self) -> bool {
+        self.get_bool("Hidden")
+    }
+
+    pub fn no_display(&self) -> bool {
+        self.get_bool("NoDisplay")
+    }
+
+    /// Whether the entry describes an installed application, `Hidden` entries
+    /// standing for deleted ones.
+    pub fn is_application(&self) -> bool {
+        self.main_group().is_some()
+            && !self.hidden()
+            && matches!(self.entry_type().as_deref(), None | Some("Application"))
+    }
+}
+
+fn is_valid_key(key: &str) -> bool {
+    let base = match key.split_once('[') {
+        Some((base, locale)) if locale.ends_with(']') => base,
+        Some(_) => return false,
+        None => key,
+    };
+    !base.is_empty() && base.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Expands the `\s`, `\n`, `\t`, `\r` and `\\` escape sequences.
+pub fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => unescaped.push(' '),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Splits a `;` separated list, `\;` standing for a literal semicolon.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(';') => item.push(';'),
+                Some(other) => {
+                    item.push('\\');
+                    item.push(other);
+                }
+                None => item.push('\\'),
+            },
+            ';' => items.push(unescape(&std::mem::take(&mut item))),
+            c => item.push(c),
+        }
+    }
+    if !item.is_empty() {
+        items.push(unescape(&item));
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRY: &str = "\
+# comment
+[Desktop Entry]
+Type=Application
+Name=Files
+Name[de]=Dateien
+Name[sr_YU]=Datoteke
+Name[sr@Latn]=Fajlovi
+Exec=nautilus --new-window %U
+Keywords=folder;manager\\;explore;disk\\s;
+Name=Duplicate
+
+[Desktop Action new-window]
+Name=New Window
+Icon=action-icon
+";
+
+    fn locale(s: &str) -> Locale {
+        Locale::parse(s).expect("invalid locale")
+    }
+
+    #[test]
+    fn locale_candidates_are_most_specific_first() {
+        assert_eq!(
+            locale("sr_YU.UTF-8@Latn").candidates(),
+            vec!["sr_YU@Latn", "sr_YU", "sr@Latn", "sr"]
+        );
+        assert_eq!(locale("de_DE.UTF-8").candidates(), vec!["de_DE", "de"]);
+        assert_eq!(locale("fr").candidates(), vec!["fr"]);
+        assert_eq!(Locale::parse("C"), None);
+        assert_eq!(Locale::parse("POSIX.UTF-8"), None);
+    }
+
+    #[test]
+    fn unescape_expands_known_sequences_only() {
+        assert_eq!(unescape(r"a\sb\nc\td\re\\f"), "a b\nc\td\re\\f");
+        assert_eq!(unescape(r"keep\x"), r"keep\x");
+        assert_eq!(unescape("trailing\\"), "trailing\\");
+    }
+
+    #[test]
+    fn split_list_honors_escaped_semicolons() {
+        assert_eq!(split_list("a;b\\;c;d\\s;"), vec!["a", "b;c", "d "]);
+        assert_eq!(split_list(r"x\\;y"), vec![r"x\", "y"]);
+        assert_eq!(split_list("single"), vec!["single"]);
+        assert!(split_list("").is_empty());
+    }
+
+    #[test]
+    fn get_localized_falls_back_to_less_specific_keys() {
+        let entry = DesktopEntry::parse(ENTRY);
+        let get = |s: &str| entry.get_localized("Name", Some(&locale(s)));
+        assert_eq!(get("sr_YU@Latn").as_deref(), Some("Datoteke"));
+        assert_eq!(get("sr_BA@Latn").as_deref(), Some("Fajlovi"));
+        assert_eq!(get("de_AT").as_deref(), Some("Dateien"));
+        assert_eq!(get("fr_FR").as_deref(), Some("Files"));
+        assert_eq!(entry.get_localized("Name", None).as_deref(), Some("Files"));
+    }
+
+    #[test]
+    fn keys_are_read_from_the_main_group_only() {
+        let entry = DesktopEntry::parse(ENTRY);
+        assert_eq!(entry.icon(), None);
+        assert_eq!(entry.exec().as_deref(), Some("nautilus --new-window %U"));
+        assert_eq!(
+            entry
+                .group("Desktop Action new-window")
+                .and_then(|group| group.get("Icon")),
+            Some(String::from("action-icon"))
+        );
+        assert_eq!(
+            entry
+                .group(MAIN_GROUP)
+                .map(|group| group.get_list("Keywords")),
+            Some(vec![
+                String::from("folder"),
+                String::from("manager;explore"),
+                String::from("disk "),
+            ])
+        );
+    }
+
+    #[test]
+    fn is_application_checks_type_and_hidden() {
+        assert!(DesktopEntry::parse(ENTRY).is_application());
+        assert!(DesktopEntry::parse("[Desktop Entry]\nName=NoType").is_application());
+        assert!(!DesktopEntry::parse("[Desktop Entry]\nType=Link").is_application());
+        assert!(!DesktopEntry::parse("[Desktop Entry]\nHidden=true").is_application());
+        assert!(!DesktopEntry::parse("[Other]\nType=Application").is_application());
+    }
+}
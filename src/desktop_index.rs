@@ -0,0 +1,348 @@
+use crate::desktop_entry::DesktopEntry;
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use ignore::WalkBuilder;
+use rayon::prelude::*;
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// SkimMatcherV2 scores a contiguous match around 20 per character, scattered
+/// letters (e.g. "Code" in "Color Picker Dev Engine") fall below.
+const MIN_FUZZY_SCORE_PER_CHAR: i64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    StartupWmClass,
+    DesktopId,
+    Exec,
+    Fuzzy(i64),
+}
+
+impl fmt::Display for MatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchKind::StartupWmClass => write!(f, "StartupWMClass"),
+            MatchKind::DesktopId => write!(f, "desktop-file ID"),
+            MatchKind::Exec => write!(f, "Exec"),
+            MatchKind::Fuzzy(score) => write!(f, "Name score {}", score),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct IndexedEntry {
+    /// Desktop-file ID: the path relative to its applications directory, with `/`
+    /// replaced by `-` (e.g. `kde4-konsole.desktop`).
+    pub id: String,
+    pub path: PathBuf,
+    /// Unlocalized `Name`, the closest to what applications use as their class.
+    pub name: String,
+    pub icon: Option<String>,
+    pub entry: DesktopEntry,
+}
+
+/// Every desktop entry of the applications directories, parsed once and shared by
+/// all the icon lookups of a run.
+#[derive(Debug, Default)]
+pub struct DesktopIndex {
+    entries: Vec<IndexedEntry>,
+}
+
+fn parse_entry((id, path): (String, PathBuf)) -> Option<IndexedEntry> {
+    let entry = DesktopEntry::read(&path).ok()?;
+    if !entry.is_application() {
+        return None;
+    }
+    Some(IndexedEntry {
+        id,
+        path,
+        name: entry.name()?,
+        icon: entry.icon(),
+        entry,
+    })
+}
+
+impl DesktopIndex {
+    /// Indexes the directories in order of precedence: an entry shadows the ones
+    /// sharing its desktop-file ID in the following directories, even when it is
+    /// `Hidden` (which is how a user entry deletes a system one).
+    pub fn build<P: AsRef<Path>>(dirs: &[P]) -> Self {
+        let mut ids: HashSet<String> = HashSet::new();
+        let paths: Vec<(String, PathBuf)> = dirs
+            .iter()
+            .flat_map(|dir| {
+                let dir = dir.as_ref();
+                WalkBuilder::new(dir)
+                    .build()
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.into_path())
+                    .filter(|path| {
+                        path.is_file() && path.extension().is_some_and(|ext| ext == "desktop")
+                    })
+                    .filter_map(move |path| {
+                        let id = path.strip_prefix(dir).ok()?.to_str()?.replace('/', "-");
+                        Some((id, path))
+                    })
+            })
+            .filter(|(id, _)| ids.insert(id.clone()))
+            .collect();
+        DesktopIndex {
+            entries: paths.into_par_iter().filter_map(parse_entry).collect(),
+        }
+    }
+
+    pub fn entries(&self) -> &[IndexedEntry] {
+        &self.entries
+    }
+
+    /// Resolves the desktop entry of a window, trying in order:
+    /// 1. an exact `StartupWMClass` match on the class or instance
+    /// 2. a desktop-file ID equal to the lowercase class or instance
+    /// 3. the program of `Exec` matching the window process executable
+    /// 4. the best fuzzy match of the class on `Name`, above a minimum score
+    pub fn find_by_window<F>(
+        &self,
+        class: &str,
+        instance: Option<&str>,
+        exe: F,
+    ) -> Option<(&IndexedEntry, MatchKind)>
+    where
+        F: FnOnce() -> Option<PathBuf>,
+    {
+        let identifiers: Vec<&str> = std::iter::once(class).chain(instance).collect();
+        if let Some(entry) = self
+            .entries
+            .iter()
+            .find(|entry| matches_startup_wm_class(entry, &identifiers))
+        {
+            return Some((entry, MatchKind::StartupWmClass));
+        }
+        if let Some(entry) = identifiers.iter().find_map(|identifier| {
+            self.entries
+                .iter()
+                .find(|entry| matches_desktop_id(entry, identifier))
+        }) {
+            return Some((entry, MatchKind::DesktopId));
+        }
+        if let Some(exe_name) = exe().and_then(|exe| exe.file_name().map(|name| name.to_owned())) {
+            if let Some(entry) = self.entries.iter().find(|entry| {
+                entry
+                    .entry
+                    .exec()
+                    .and_then(|exec| get_exec_program(&exec))
+                    .is_some_and(|program| Path::new(&program).file_name() == Some(&exe_name))
+            }) {
+                return Some((entry, MatchKind::Exec));
+            }
+        }
+        self.find_by_class(class)
+            .map(|(entry, score)| (entry, MatchKind::Fuzzy(score)))
+    }
+
+    /// Returns the entry whose `Name` is the best fuzzy match of the class, ties
+    /// going to the shortest name then to the smallest desktop-file ID.
+    pub fn find_by_class(&self, class: &str) -> Option<(&IndexedEntry, i64)> {
+        let matcher = SkimMatcherV2::ignore_case(SkimMatcherV2::default());
+        let min_score = get_min_fuzzy_score(class);
+        self.entries
+            .iter()
+            .filter_map(|entry| {
+                matcher
+                    .fuzzy_match(&entry.name, class)
+                    .filter(|score| *score >= min_score)
+                    .map(|score| (entry, score))
+            })
+            .min_by(|(a, a_score), (b, b_score)| {
+                b_score
+                    .cmp(a_score)
+                    .then(a.name.len().cmp(&b.name.len()))
+                    .then(a.id.cmp(&b.id))
+            })
+    }
+
+    /// Returns every entry matching the window in some way, with all the ways it
+    /// does, for diagnostics. Fuzzy scores are reported even below the minimum.
+    pub fn find_candidates(
+        &self,
+        class: &str,
+        instance: Option<&str>,
+    ) -> Vec<(&IndexedEntry, Vec<MatchKind>)> {
+        let identifiers: Vec<&str> = std::iter::once(class).chain(instance).collect();
+        let matcher = SkimMatcherV2::ignore_case(SkimMatcherV2::default());
+        let mut candidates: Vec<(&IndexedEntry, Vec<MatchKind>)> = self
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                let mut kinds: Vec<MatchKind> = Vec::new();
+                if matches_startup_wm_class(entry, &identifiers) {
+                    kinds.push(MatchKind::StartupWmClass);
+                }
+                if identifiers
+                    .iter()
+                    .any(|identifier| matches_desktop_id(entry, identifier))
+                {
+                    kinds.push(MatchKind::DesktopId);
+                }
+                if let Some(score) = matcher.fuzzy_match(&entry.name, class) {
+                    kinds.push(MatchKind::Fuzzy(score));
+                }
+                (!kinds.is_empty()).then_some((entry, kinds))
+            })
+            .collect();
+        candidates.sort_by_key(|(entry, kinds)| {
+            (
+                !kinds.contains(&MatchKind::StartupWmClass),
+                !kinds.contains(&MatchKind::DesktopId),
+                std::cmp::Reverse(kinds.iter().find_map(|kind| match kind {
+                    MatchKind::Fuzzy(score) => Some(*score),
+                    _ => None,
+                })),
+                entry.name.len(),
+            )
+        });
+        candidates
+    }
+}
+
+/// Fuzzy scores of the class below this are ignored.
+pub fn get_min_fuzzy_score(class: &str) -> i64 {
+    MIN_FUZZY_SCORE_PER_CHAR * class.chars().count() as i64
+}
+
+fn matches_startup_wm_class(entry: &IndexedEntry, identifiers: &[&str]) -> bool {
+    entry
+        .entry
+        .startup_wm_class()
+        .is_some_and(|wm_class| identifiers.contains(&wm_class.as_str()))
+}
+
+/// Whether the desktop-file ID is the lowercase identifier.
+fn matches_desktop_id(entry: &IndexedEntry, identifier: &str) -> bool {
+    entry.id.to_lowercase() == format!("{}.desktop", identifier.to_lowercase())
+}
+
+/// Returns the program of an `Exec` value, skipping an `env VAR=value` prefix.
+fn get_exec_program(exec: &str) -> Option<String> {
+    let mut tokens = exec.split_whitespace().map(|token| token.trim_matches('"'));
+    let mut program = tokens.next()?;
+    if program == "env" {
+        program = tokens.find(|token| !token.contains('='))?;
+    }
+    Some(program.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_index(entries: &[(&str, &str)]) -> DesktopIndex {
+        DesktopIndex {
+            entries: entries
+                .iter()
+                .map(|(id, keys)| {
+                    let entry = DesktopEntry::parse(&format!(
+                        "[Desktop Entry]\nType=Application\n{}",
+                        keys
+                    ));
+                    IndexedEntry {
+                        id: id.to_string(),
+                        path: PathBuf::from("/usr/share/applications").join(id),
+                        name: entry.name().expect("entry without Name"),
+                        icon: entry.icon(),
+                        entry,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    fn resolve<'a>(
+        index: &'a DesktopIndex,
+        class: &str,
+        instance: Option<&str>,
+        exe: Option<&str>,
+    ) -> Option<(&'a str, MatchKind)> {
+        index
+            .find_by_window(class, instance, || exe.map(PathBuf::from))
+            .map(|(entry, kind)| (entry.id.as_str(), kind))
+    }
+
+    #[test]
+    fn startup_wm_class_wins_over_desktop_id() {
+        let index = build_index(&[
+            ("firefox.desktop", "Name=Firefox"),
+            (
+                "firefox-developer.desktop",
+                "Name=Firefox Developer Edition\nStartupWMClass=firefox",
+            ),
+        ]);
+        assert_eq!(
+            resolve(&index, "firefox", Some("Navigator"), None),
+            Some(("firefox-developer.desktop", MatchKind::StartupWmClass))
+        );
+    }
+
+    #[test]
+    fn desktop_id_matches_the_lowercase_class_or_instance() {
+        let index = build_index(&[
+            ("org.gnome.Nautilus.desktop", "Name=Files"),
+            ("gimp.desktop", "Name=GNU Image Manipulation Program"),
+        ]);
+        assert_eq!(
+            resolve(&index, "Org.Gnome.Nautilus", None, None),
+            Some(("org.gnome.Nautilus.desktop", MatchKind::DesktopId))
+        );
+        assert_eq!(
+            resolve(&index, "Gimp-2.10", Some("gimp"), None),
+            Some(("gimp.desktop", MatchKind::DesktopId))
+        );
+    }
+
+    #[test]
+    fn exec_program_skips_env_prefix() {
+        assert_eq!(
+            get_exec_program("env GDK_BACKEND=x11 LANG=C /usr/bin/foo --bar %U").as_deref(),
+            Some("/usr/bin/foo")
+        );
+        assert_eq!(get_exec_program("\"foo\" %F").as_deref(), Some("foo"));
+        assert_eq!(get_exec_program("env A=1"), None);
+        let index = build_index(&[("bar.desktop", "Name=Bar\nExec=env A=1 foo %U")]);
+        assert_eq!(
+            resolve(&index, "Something", None, Some("/usr/lib/foo")),
+            Some(("bar.desktop", MatchKind::Exec))
+        );
+    }
+
+    #[test]
+    fn scattered_fuzzy_matches_are_ignored() {
+        let picker = ("color-picker.desktop", "Name=Color Picker Dev Engine");
+        assert!(build_index(&[picker]).find_by_class("Code").is_none());
+        let index = build_index(&[picker, ("code.desktop", "Name=Visual Studio Code")]);
+        assert_eq!(
+            index
+                .find_by_class("Code")
+                .map(|(entry, _)| entry.id.as_str()),
+            Some("code.desktop")
+        );
+    }
+
+    #[test]
+    fn fuzzy_ties_go_to_the_shortest_name_then_the_smallest_id() {
+        let find = |index: &DesktopIndex| {
+            index
+                .find_by_class("Terminal")
+                .map(|(entry, _)| entry.id.clone())
+        };
+        let emulator = ("terminal-emulator.desktop", "Name=Terminal Emulator");
+        let index = build_index(&[emulator, ("b-terminal.desktop", "Name=Terminal")]);
+        assert_eq!(find(&index).as_deref(), Some("b-terminal.desktop"));
+        let index = build_index(&[
+            emulator,
+            ("b-terminal.desktop", "Name=Terminal"),
+            ("a-terminal.desktop", "Name=Terminal"),
+        ]);
+        assert_eq!(find(&index).as_deref(), Some("a-terminal.desktop"));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::{
+    external_command::get_window_pid,
+    formatter::NodeInfo,
+    utils::{
+        i3_tree::{find_workspace, get_child_iter},
+        proc::{read_cmdline, read_cwd},
+        time::now,
+    },
+};
+use i3_ipc::reply::Node;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Windows kept in the journal, the oldest ones being dropped first.
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub timestamp: u64,
+    pub class: String,
+    pub title: String,
+    pub icon: String,
+    pub workspace: Option<String>,
+    pub rect: HistoryRect,
+    pub pid: Option<u32>,
+    pub cmdline: Vec<String>,
+    pub cwd: Option<String>,
+    /// Layout saved before the kill, shared by every window of the same kill.
+    #[serde(default)]
+    pub layout: Option<PathBuf>,
+}
+
+/// Snapshots every window of the target subtree, must be called before the kill
+/// since the process information is read from `/proc`.
+pub fn collect_entries(
+    node: &Node,
+    tree: &Node,
+    nodes_info: &[NodeInfo],
+    layout: Option<PathBuf>,
+) -> Vec<HistoryEntry> {
+    fn build_entries(
+        node: &Node,
+        workspace: &Option<String>,
+        timestamp: u64,
+        nodes_info: &[NodeInfo],
+        layout: &Option<PathBuf>,
+    ) -> Vec<HistoryEntry> {
+        let mut entries: Vec<HistoryEntry> = Vec::new();
+        if let Some(info) = nodes_info.iter().find(|info| info.id == node.id) {
+            let pid = node.window.and_then(get_window_pid);
+            entries.push(HistoryEntry {
+                timestamp,
+                class: info.class.clone(),
+                title: info.title.clone(),
+                icon: info.icon.clone(),
+                workspace: workspace.clone(),
+                rect: HistoryRect {
+                    x: node.rect.x as i32,
+                    y: node.rect.y as i32,
+                    width: node.rect.width as i32,
+                    height: node.rect.height as i32,
+                },
+                pid,
+                cmdline: pid.map(read_cmdline).unwrap_or_default(),
+                cwd: pid.and_then(read_cwd),
+                layout: layout.clone(),
+            });
+        }
+        get_child_iter(node).for_each(|node| {
+            entries.append(build_entries(node, workspace, timestamp, nodes_info, layout).as_mut())
+        });
+        entries
+    }
+    let timestamp = now();
+    let workspace = find_workspace(node, tree).and_then(|ws| ws.name.clone());
+    build_entries(node, &workspace, timestamp, nodes_info, &layout)
+}
+
+pub fn append_entries(path: &Path, entries: &[HistoryEntry]) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    for entry in entries {
+        let line = serde_json::to_string(entry)?;
+        writeln!(file, "{}", line)?;
+    }
+    trim_entries(path, MAX_ENTRIES)
+}
+
+/// Drops the oldest entries beyond `max_entries`, along with the layouts only they
+/// referenced.
+fn trim_entries(path: &Path, max_entries: usize) -> io::Result<()> {
+    let text = fs::read_to_string(path)?;
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.len() <= max_entries {
+        return Ok(());
+    }
+    let (dropped, kept) = lines.split_at(lines.len() - max_entries);
+    let get_layouts = |lines: &[&str]| -> Vec<PathBuf> {
+        lines
+            .iter()
+            .filter_map(|line| serde_json::from_str::<HistoryEntry>(line).ok())
+            .filter_map(|entry| entry.layout)
+            .collect()
+    };
+    let kept_layouts = get_layouts(kept);
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, format!("{}\n", kept.join("\n")))?;
+    fs::rename(&tmp_path, path)?;
+    for layout in get_layouts(dropped) {
+        if !kept_layouts.contains(&layout) && layout.is_file() {
+            fs::remove_file(&layout)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the journal entries, most recent first.
+pub fn read_entries(path: &Path) -> io::Result<Vec<HistoryEntry>> {
+    let text = fs::read_to_string(path)?;
+    Ok(text
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str::<HistoryEntry>(line).ok())
+        .collect())
+}
+
+/// Returns the windows of the most recent kill whose layout hasn't been restored yet.
+pub fn last_undoable(entries: Vec<HistoryEntry>) -> Vec<HistoryEntry> {
+    let layout = entries
+        .iter()
+        .find_map(|entry| entry.layout.as_ref().filter(|path| path.is_file()).cloned());
+    match layout {
+        Some(layout) => entries
+            .into_iter()
+            .skip_while(|entry| entry.layout.as_ref() != Some(&layout))
+            .take_while(|entry| entry.layout.as_ref() == Some(&layout))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    /// An empty directory of the temporary directory, unique to the test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("i3-window-killer-{}-{}", process::id(), name));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).expect("couldn't create the temporary directory");
+        dir
+    }
+
+    fn entry(class: &str, layout: Option<&Path>) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: 0,
+            class: class.to_owned(),
+            title: String::new(),
+            icon: String::new(),
+            workspace: None,
+            rect: HistoryRect {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            },
+            pid: None,
+            cmdline: Vec::new(),
+            cwd: None,
+            layout: layout.map(Path::to_path_buf),
+        }
+    }
+
+    fn classes(entries: &[HistoryEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.class.as_str()).collect()
+    }
+
+    #[test]
+    fn last_undoable_skips_restored_layouts() {
+        let dir = temp_dir("undoable");
+        let (restored, saved) = (dir.join("2-restored.json"), dir.join("1-saved.json"));
+        fs::write(&saved, "{}").unwrap();
+        // most recent first, as read from the journal
+        let entries = vec![
+            entry("Firefox", None),
+            entry("Alacritty", Some(&restored)),
+            entry("Code", Some(&saved)),
+            entry("Code", Some(&saved)),
+            entry("Gimp", Some(&saved)),
+            entry("mpv", None),
+        ];
+        let undoable = last_undoable(entries);
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(classes(&undoable), vec!["Code", "Code", "Gimp"]);
+        assert!(last_undoable(vec![entry("Firefox", None)]).is_empty());
+    }
+
+    #[test]
+    fn trim_entries_drops_the_oldest_and_their_layouts() {
+        let dir = temp_dir("trim");
+        let journal = dir.join("history.jsonl");
+        let (dropped, shared) = (dir.join("1-dropped.json"), dir.join("2-shared.json"));
+        fs::write(&dropped, "{}").unwrap();
+        fs::write(&shared, "{}").unwrap();
+        append_entries(
+            &journal,
+            &[
+                entry("Firefox", Some(&dropped)),
+                entry("Code", Some(&shared)),
+                entry("Code", Some(&shared)),
+                entry("mpv", None),
+            ],
+        )
+        .unwrap();
+        trim_entries(&journal, 2).unwrap();
+        let entries = read_entries(&journal).unwrap();
+        let layouts_left = (dropped.exists(), shared.exists());
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(classes(&entries), vec!["mpv", "Code"]);
+        assert_eq!(layouts_left, (false, true));
+    }
+}
@@ -0,0 +1,181 @@
+//! Theme values of the i3 config, as returned by `GET_CONFIG`.
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Colors of a `client.<class>` directive.
+#[derive(Debug, Clone, Serialize)]
+pub struct ColorClass {
+    pub border: String,
+    pub background: String,
+    pub text: String,
+    pub indicator: Option<String>,
+    /// Defaults to the background, like i3 does.
+    pub child_border: String,
+}
+
+/// The `client.*` directives, by class without the `client.` prefix (`focused`,
+/// `focused_inactive`, `unfocused`, `urgent`...).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Colors {
+    #[serde(flatten)]
+    pub classes: HashMap<String, ColorClass>,
+    /// `client.background`, the only class holding a single color.
+    pub background: Option<String>,
+}
+
+/// The `i3` template variable.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct I3Config {
+    pub colors: Colors,
+    /// Font of the title bars, without its `pango:` prefix.
+    pub font: Option<String>,
+    /// `set $<name> <value>` variables, by name without the `$`.
+    pub vars: HashMap<String, String>,
+}
+
+impl I3Config {
+    /// Included files aren't followed, only the main config is parsed.
+    pub fn parse(text: &str) -> Self {
+        let lines = join_continued_lines(text);
+        let mut config = I3Config::default();
+        for line in lines.iter() {
+            let mut words = line.split_whitespace();
+            let (name, value) = match (words.next(), words.next()) {
+                (Some("set"), Some(name)) => {
+                    let value = words.collect::<Vec<&str>>().join(" ");
+                    (name, value)
+                }
+                // the fallback value stands for the X resource
+                (Some("set_from_resource"), Some(name)) => {
+                    let value = words.skip(1).collect::<Vec<&str>>().join(" ");
+                    (name, value)
+                }
+                _ => continue,
+            };
+            if let Some(name) = name.strip_prefix('$') {
+                let value = config.expand(&value);
+                config.vars.insert(name.to_owned(), value);
+            }
+        }
+        for line in lines.iter() {
+            let line = config.expand(line);
+            let (directive, arguments) = match line.split_once(char::is_whitespace) {
+                Some((directive, arguments)) => (directive, arguments.trim()),
+                None => continue,
+            };
+            if directive == "font" {
+                let font = arguments.strip_prefix("pango:").unwrap_or(arguments);
+                config.font = Some(font.trim().to_owned());
+            } else if directive == "client.background" {
+                config.colors.background = arguments.split_whitespace().next().map(str::to_owned);
+            } else if let Some(class) = directive.strip_prefix("client.") {
+                let colors: Vec<&str> = arguments.split_whitespace().collect();
+                if colors.len() < 3 {
+                    continue;
+                }
+                config.colors.classes.insert(
+                    class.to_owned(),
+                    ColorClass {
+                        border: colors[0].to_owned(),
+                        background: colors[1].to_owned(),
+                        text: colors[2].to_owned(),
+                        indicator: colors.get(3).map(|color| color.to_string()),
+                        child_border: colors.get(4).unwrap_or(&colors[1]).to_string(),
+                    },
+                );
+            }
+        }
+        config
+    }
+
+    /// Replaces the variables, longest names first so `$bg` doesn't eat `$bg_alt`.
+    fn expand(&self, value: &str) -> String {
+        let mut names: Vec<&String> = self.vars.keys().collect();
+        names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+        names.into_iter().fold(value.to_owned(), |value, name| {
+            value.replace(&format!("${}", name), &self.vars[name])
+        })
+    }
+}
+
+/// Trims the lines, drops comments and joins the ones ending with a backslash.
+fn join_continued_lines(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut pending = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if pending.is_empty() && (line.is_empty() || line.starts_with('#')) {
+            continue;
+        }
+        match line.strip_suffix('\\') {
+            Some(line) => {
+                pending.push_str(line.trim_end());
+                pending.push(' ');
+            }
+            None => {
+                pending.push_str(line);
+                lines.push(std::mem::take(&mut pending));
+            }
+        }
+    }
+    if !pending.is_empty() {
+        lines.push(pending);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+# theme
+set $bg #222222
+set $bg_alt #444444
+set_from_resource $fg i3wm.foreground #eeeeee
+font pango:DejaVu Sans \
+    Mono 10
+
+client.focused $bg_alt $bg $fg #2e9ef4 #285577
+client.unfocused $bg $bg_alt $fg
+client.urgent #2f343a
+client.background $bg
+"#;
+
+    #[test]
+    fn variables_are_expanded_longest_first() {
+        let config = I3Config::parse(CONFIG);
+        assert_eq!(config.vars["bg"], "#222222");
+        assert_eq!(config.vars["bg_alt"], "#444444");
+        let focused = &config.colors.classes["focused"];
+        assert_eq!(focused.border, "#444444");
+        assert_eq!(focused.background, "#222222");
+        assert_eq!(config.colors.background.as_deref(), Some("#222222"));
+    }
+
+    #[test]
+    fn resources_fall_back_to_their_default() {
+        let config = I3Config::parse(CONFIG);
+        assert_eq!(config.vars["fg"], "#eeeeee");
+        assert_eq!(config.colors.classes["focused"].text, "#eeeeee");
+    }
+
+    #[test]
+    fn continued_lines_are_joined_and_pango_prefix_stripped() {
+        let config = I3Config::parse(CONFIG);
+        assert_eq!(config.font.as_deref(), Some("DejaVu Sans Mono 10"));
+    }
+
+    #[test]
+    fn child_border_defaults_to_background() {
+        let config = I3Config::parse(CONFIG);
+        let focused = &config.colors.classes["focused"];
+        assert_eq!(focused.indicator.as_deref(), Some("#2e9ef4"));
+        assert_eq!(focused.child_border, "#285577");
+        let unfocused = &config.colors.classes["unfocused"];
+        assert_eq!(unfocused.indicator, None);
+        assert_eq!(unfocused.child_border, "#444444");
+        assert!(!config.colors.classes.contains_key("urgent"));
+    }
+}
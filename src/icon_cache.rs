@@ -0,0 +1,248 @@
+use crate::utils::time::{now, to_timestamp};
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const CACHE_VERSION: u32 = 1;
+/// Failed lookups are retried after this delay, in case a matching application
+/// got installed in the meantime.
+pub const NEGATIVE_ENTRY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct CacheKey {
+    pub class: String,
+    pub instance: Option<String>,
+}
+
+impl CacheKey {
+    /// Key of the entries set by hand, which apply to every instance of the class.
+    pub fn from_class(class: &str) -> Self {
+        CacheKey {
+            class: class.to_owned(),
+            instance: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    #[serde(flatten)]
+    pub key: CacheKey,
+    /// `None` records a failed lookup.
+    pub icon: Option<String>,
+    pub desktop_file: Option<PathBuf>,
+    pub desktop_file_mtime: Option<u64>,
+    pub updated: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: Vec<CacheEntry>,
+}
+
+pub fn get_mtime(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(to_timestamp)
+}
+
+impl CacheEntry {
+    pub fn new(key: CacheKey, icon: Option<String>, desktop_file: Option<PathBuf>) -> Self {
+        CacheEntry {
+            key,
+            icon,
+            desktop_file_mtime: desktop_file.as_deref().and_then(get_mtime),
+            desktop_file,
+            updated: now(),
+        }
+    }
+
+    /// Entries set with `cache set` have an icon but no desktop file.
+    pub fn is_set_by_hand(&self) -> bool {
+        self.icon.is_some() && self.desktop_file.is_none()
+    }
+
+    /// Whether the entry can still be trusted: negative entries expire, the other
+    /// ones last as long as their desktop file is left untouched.
+    pub fn is_valid(&self) -> bool {
+        match (&self.icon, &self.desktop_file) {
+            (None, _) => now() < self.updated + NEGATIVE_ENTRY_TTL.as_secs(),
+            (Some(_), Some(desktop_file)) => {
+                let mtime = get_mtime(desktop_file);
+                mtime.is_some() && mtime == self.desktop_file_mtime
+            }
+            (Some(_), None) => true,
+        }
+    }
+}
+
+/// Icon names resolved by previous runs. Changes are kept aside and merged into the
+/// file under a lock on `save`, so concurrent runs don't overwrite each other.
+#[derive(Debug)]
+pub struct IconCache {
+    path: PathBuf,
+    mtime: Option<u64>,
+    entries: HashMap<CacheKey, CacheEntry>,
+    changes: HashMap<CacheKey, Option<CacheEntry>>,
+}
+
+fn read_entries(path: &Path) -> HashMap<CacheKey, CacheEntry> {
+    // files from older versions (`class=icon` lines) are discarded
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str::<CacheFile>(&text).ok())
+        .filter(|file| file.version == CACHE_VERSION)
+        .map(|file| {
+            file.entries
+                .into_iter()
+                .map(|entry| (entry.key.clone(), entry))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl IconCache {
+    pub fn load(path: PathBuf) -> Self {
+        IconCache {
+            mtime: get_mtime(&path),
+            entries: read_entries(&path),
+            changes: HashMap::new(),
+            path,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reloads the file if another process wrote to it since it was read, returning
+    /// whether it did.
+    pub fn refresh(&mut self) -> bool {
+        let mtime = get_mtime(&self.path);
+        let changed = mtime != self.mtime;
+        if changed {
+            self.mtime = mtime;
+            self.entries = read_entries(&self.path);
+            for (key, change) in self.changes.iter() {
+                match change {
+                    Some(entry) => self.entries.insert(key.clone(), entry.clone()),
+                    None => self.entries.remove(key),
+                };
+            }
+        }
+        changed
+    }
+
+    /// Returns the entry of the key, if it is still valid.
+    pub fn get(&self, key: &CacheKey) -> Option<&CacheEntry> {
+        self.entries.get(key).filter(|entry| entry.is_valid())
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &CacheEntry> {
+        self.entries.values()
+    }
+
+    pub fn insert(&mut self, entry: CacheEntry) {
+        self.changes.insert(entry.key.clone(), Some(entry.clone()));
+        self.entries.insert(entry.key.clone(), entry);
+    }
+
+    pub fn remove(&mut self, key: &CacheKey) -> Option<CacheEntry> {
+        self.changes.insert(key.clone(), None);
+        self.entries.remove(key)
+    }
+
+    /// Removes every entry of the class, whatever their instance.
+    pub fn remove_class(&mut self, class: &str) -> Vec<CacheEntry> {
+        let keys: Vec<CacheKey> = self
+            .entries
+            .keys()
+            .filter(|key| key.class == class)
+            .cloned()
+            .collect();
+        keys.iter().filter_map(|key| self.remove(key)).collect()
+    }
+
+    /// Removes the entries that are no longer valid, e.g. whose desktop file vanished.
+    pub fn prune(&mut self) -> Vec<CacheEntry> {
+        let keys: Vec<CacheKey> = self
+            .entries
+            .values()
+            .filter(|entry| !entry.is_valid())
+            .map(|entry| entry.key.clone())
+            .collect();
+        keys.iter().filter_map(|key| self.remove(key)).collect()
+    }
+
+    /// Removes the entries a rescan of the desktop files may have changed: the failed
+    /// lookups, and the ones whose desktop file is no longer indexed because it got
+    /// shadowed, hidden or deleted. Entries set by hand are kept.
+    pub fn invalidate<F>(&mut self, is_indexed: F) -> Vec<CacheEntry>
+    where
+        F: Fn(&Path) -> bool,
+    {
+        let keys: Vec<CacheKey> = self
+            .entries
+            .values()
+            .filter(|entry| match (&entry.icon, &entry.desktop_file) {
+                (None, _) => true,
+                (Some(_), Some(desktop_file)) => !is_indexed(desktop_file),
+                (Some(_), None) => false,
+            })
+            .map(|entry| entry.key.clone())
+            .collect();
+        keys.iter().filter_map(|key| self.remove(key)).collect()
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        !self.changes.is_empty()
+    }
+
+    /// Merges the changes into the file, which is replaced atomically.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.is_dirty() {
+            return Ok(());
+        }
+        let lock = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(self.path.with_extension("lock"))?;
+        lock.lock_exclusive()?;
+        let mut entries = read_entries(&self.path);
+        for (key, change) in self.changes.drain() {
+            match change {
+                Some(entry) => entries.insert(key, entry),
+                None => entries.remove(&key),
+            };
+        }
+        let mut sorted_entries: Vec<CacheEntry> = entries.values().cloned().collect();
+        sorted_entries.sort_by(|a, b| a.key.cmp(&b.key));
+        let tmp_path = self.path.with_extension("tmp");
+        {
+            let mut file = File::create(&tmp_path)?;
+            serde_json::to_writer_pretty(
+                &mut file,
+                &CacheFile {
+                    version: CACHE_VERSION,
+                    entries: sorted_entries,
+                },
+            )?;
+            file.write_all(b"\n")?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        lock.unlock()?;
+        self.mtime = get_mtime(&self.path);
+        self.entries = entries;
+        Ok(())
+    }
+}
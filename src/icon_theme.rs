@@ -0,0 +1,252 @@
+//! Icon lookup following the freedesktop [Icon Theme specification](https://specifications.freedesktop.org/icon-theme-spec/icon-theme-spec-latest.html).
+
+use crate::{
+    desktop_entry::DesktopEntry,
+    settings::IconThemeSettings,
+    utils::fs::{get_default_config_dir, get_icons_dirs},
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+pub const FALLBACK_THEME: &str = "hicolor";
+const THEME_GROUP: &str = "Icon Theme";
+const EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DirectoryType {
+    Fixed,
+    Scalable,
+    Threshold,
+}
+
+/// A sub-directory of a theme, described by its own group of `index.theme`.
+#[derive(Debug, Clone)]
+struct Directory {
+    path: String,
+    size: u32,
+    scale: u32,
+    directory_type: DirectoryType,
+    min_size: u32,
+    max_size: u32,
+    threshold: u32,
+}
+
+impl Directory {
+    fn parse(path: &str, index: &DesktopEntry) -> Option<Self> {
+        let group = index.group(path)?;
+        let get_number = |key| group.get(key).and_then(|v| v.trim().parse::<u32>().ok());
+        let size = get_number("Size")?;
+        Some(Directory {
+            path: path.to_owned(),
+            size,
+            scale: get_number("Scale").unwrap_or(1),
+            directory_type: match group.get("Type").as_deref().map(str::trim) {
+                Some("Fixed") => DirectoryType::Fixed,
+                Some("Scalable") => DirectoryType::Scalable,
+                _ => DirectoryType::Threshold,
+            },
+            min_size: get_number("MinSize").unwrap_or(size),
+            max_size: get_number("MaxSize").unwrap_or(size),
+            threshold: get_number("Threshold").unwrap_or(2),
+        })
+    }
+
+    fn matches_size(&self, size: u32, scale: u32) -> bool {
+        if self.scale != scale {
+            return false;
+        }
+        match self.directory_type {
+            DirectoryType::Fixed => self.size == size,
+            DirectoryType::Scalable => self.min_size <= size && size <= self.max_size,
+            DirectoryType::Threshold => {
+                self.size.saturating_sub(self.threshold) <= size
+                    && size <= self.size + self.threshold
+            }
+        }
+    }
+
+    fn size_distance(&self, size: u32, scale: u32) -> u32 {
+        let scaled_size = size * scale;
+        let (min, max) = match self.directory_type {
+            DirectoryType::Fixed => (self.size, self.size),
+            DirectoryType::Scalable => (self.min_size, self.max_size),
+            DirectoryType::Threshold => (
+                self.size.saturating_sub(self.threshold),
+                self.size + self.threshold,
+            ),
+        };
+        // at most one of them is non-zero
+        (min * self.scale).saturating_sub(scaled_size)
+            + scaled_size.saturating_sub(max * self.scale)
+    }
+}
+
+#[derive(Debug)]
+struct Theme {
+    /// Every base directory holding a directory of the theme.
+    roots: Vec<PathBuf>,
+    directories: Vec<Directory>,
+}
+
+impl Theme {
+    fn load(name: &str, icons_dirs: &[PathBuf]) -> Option<(Self, Vec<String>)> {
+        let roots: Vec<PathBuf> = icons_dirs
+            .iter()
+            .map(|dir| dir.join(name))
+            .filter(|root| root.is_dir())
+            .collect();
+        let index = roots
+            .iter()
+            .find_map(|root| DesktopEntry::read(root.join("index.theme")).ok())?;
+        let group = index.group(THEME_GROUP)?;
+        let split = |key| {
+            group
+                .get(key)
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(|item| item.trim().to_owned())
+                        .filter(|item| !item.is_empty())
+                        .collect::<Vec<String>>()
+                })
+                .unwrap_or_default()
+        };
+        let directories = split("Directories")
+            .into_iter()
+            .chain(split("ScaledDirectories"))
+            .filter_map(|path| Directory::parse(&path, &index))
+            .collect();
+        Some((Theme { roots, directories }, split("Inherits")))
+    }
+
+    fn find_file(&self, directory: &Directory, icon: &str) -> Option<PathBuf> {
+        self.roots.iter().find_map(|root| {
+            EXTENSIONS
+                .iter()
+                .map(|extension| {
+                    root.join(&directory.path)
+                        .join(format!("{}.{}", icon, extension))
+                })
+                .find(|path| path.is_file())
+        })
+    }
+
+    /// Returns the icon of the directories matching the size, else the closest one.
+    fn lookup(&self, icon: &str, size: u32, scale: u32) -> Option<PathBuf> {
+        if let Some(path) = self
+            .directories
+            .iter()
+            .filter(|directory| directory.matches_size(size, scale))
+            .find_map(|directory| self.find_file(directory, icon))
+        {
+            return Some(path);
+        }
+        self.directories
+            .iter()
+            .filter_map(|directory| {
+                self.find_file(directory, icon)
+                    .map(|path| (directory.size_distance(size, scale), path))
+            })
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, path)| path)
+    }
+}
+
+/// A theme and the themes it inherits from, in lookup order, `hicolor` coming last.
+/// Lookups are memoized.
+#[derive(Debug)]
+pub struct IconTheme {
+    pub name: String,
+    pub size: u32,
+    pub scale: u32,
+    icons_dirs: Vec<PathBuf>,
+    themes: Vec<Theme>,
+    lookups: HashMap<String, Option<PathBuf>>,
+}
+
+impl IconTheme {
+    pub fn load(name: &str, size: u32, scale: u32) -> Self {
+        fn load_chain(
+            name: &str,
+            icons_dirs: &[PathBuf],
+            visited: &mut Vec<String>,
+            themes: &mut Vec<Theme>,
+        ) {
+            if visited.iter().any(|visited| visited == name) {
+                return;
+            }
+            visited.push(name.to_owned());
+            if let Some((theme, parents)) = Theme::load(name, icons_dirs) {
+                themes.push(theme);
+                for parent in parents.iter() {
+                    load_chain(parent, icons_dirs, visited, themes);
+                }
+            }
+        }
+        let icons_dirs = get_icons_dirs();
+        let mut visited: Vec<String> = Vec::new();
+        let mut themes: Vec<Theme> = Vec::new();
+        load_chain(name, &icons_dirs, &mut visited, &mut themes);
+        load_chain(FALLBACK_THEME, &icons_dirs, &mut visited, &mut themes);
+        IconTheme {
+            name: name.to_owned(),
+            size,
+            scale,
+            icons_dirs,
+            themes,
+            lookups: HashMap::new(),
+        }
+    }
+
+    /// Loads the theme of the settings, else the GTK one, else `hicolor`.
+    pub fn from_settings(settings: &IconThemeSettings) -> Self {
+        let name = settings
+            .name
+            .clone()
+            .or_else(get_gtk_icon_theme)
+            .unwrap_or_else(|| FALLBACK_THEME.to_string());
+        IconTheme::load(&name, settings.size, settings.scale)
+    }
+
+    /// Returns the file of an icon name, or the icon itself when it is an absolute
+    /// path to an existing file. Unthemed icons are looked up at the root of the
+    /// base directories (e.g. `/usr/share/pixmaps`).
+    pub fn find_icon(&mut self, icon: &str) -> Option<PathBuf> {
+        if let Some(path) = self.lookups.get(icon) {
+            return path.clone();
+        }
+        let path = if Path::new(icon).is_absolute() {
+            Some(PathBuf::from(icon)).filter(|path| path.is_file())
+        } else {
+            self.themes
+                .iter()
+                .find_map(|theme| theme.lookup(icon, self.size, self.scale))
+                .or_else(|| {
+                    self.icons_dirs.iter().find_map(|dir| {
+                        EXTENSIONS
+                            .iter()
+                            .map(|extension| dir.join(format!("{}.{}", icon, extension)))
+                            .find(|path| path.is_file())
+                    })
+                })
+        };
+        self.lookups.insert(icon.to_owned(), path.clone());
+        path
+    }
+}
+
+/// Reads the icon theme of GTK applications (`gtk-icon-theme-name` in
+/// `gtk-3.0/settings.ini`), the closest thing to a desktop-wide setting under i3.
+pub fn get_gtk_icon_theme() -> Option<String> {
+    let path = get_default_config_dir()?
+        .join("gtk-3.0")
+        .join("settings.ini");
+    DesktopEntry::read(path)
+        .ok()?
+        .group("Settings")?
+        .get("gtk-icon-theme-name")
+        .map(|name| name.trim().trim_matches('"').to_owned())
+        .filter(|name| !name.is_empty())
+}
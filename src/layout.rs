@@ -0,0 +1,148 @@
+use crate::utils::{i3_tree::get_child_iter, time::now};
+use i3_ipc::reply::{Node, NodeBorder, NodeLayout, NodeType};
+use regex::escape;
+use serde_json::{json, Map, Value};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Relaunched applications rarely come back with the same title, so windows are only
+/// swallowed by class and instance.
+fn get_swallows(node: &Node) -> Value {
+    let mut criteria = Map::new();
+    if let Some(window_properties) = &node.window_properties {
+        for (key, value) in [
+            ("class", &window_properties.class),
+            ("instance", &window_properties.instance),
+        ] {
+            if let Some(value) = value {
+                criteria.insert(key.into(), format!("^{}$", escape(value)).into());
+            }
+        }
+    }
+    json!([criteria])
+}
+
+/// Serializes a node into a placeholder container as understood by `append_layout`.
+/// Windows become leaves swallowing their class/instance, containers keep their
+/// layout and proportions.
+fn serialize_node(node: &Node) -> Value {
+    let mut value = json!({
+        "type": "con",
+        "border": match node.border {
+            NodeBorder::Normal => "normal",
+            NodeBorder::None => "none",
+            NodeBorder::Pixel => "pixel",
+        },
+        "current_border_width": node.current_border_width,
+    });
+    if let Some(percent) = node.percent {
+        value["percent"] = percent.into();
+    }
+    if node.window_properties.is_some() {
+        if let Some(ref name) = node.name {
+            value["name"] = name.as_str().into();
+        }
+        value["swallows"] = get_swallows(node);
+    } else {
+        value["layout"] = match node.layout {
+            NodeLayout::SplitV => "splitv",
+            NodeLayout::Stacked => "stacked",
+            NodeLayout::Tabbed => "tabbed",
+            _ => "splith",
+        }
+        .into();
+        value["nodes"] = node.nodes.iter().map(serialize_node).collect();
+    }
+    value
+}
+
+/// Returns the top-level containers of the layout: the workspace children when
+/// targeting a workspace, the node itself otherwise. Floating windows have no
+/// placeholder and are simply relaunched.
+pub fn serialize_layout(node: &Node) -> Vec<Value> {
+    if node.node_type == NodeType::Workspace {
+        node.nodes.iter().map(serialize_node).collect()
+    } else {
+        vec![serialize_node(node)]
+    }
+}
+
+pub fn has_windows(node: &Node) -> bool {
+    node.window_properties.is_some() || get_child_iter(node).any(has_windows)
+}
+
+pub fn save_layout(dir: &Path, node: &Node) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}-{}.json", now(), node.id));
+    let mut file = fs::File::create(&path)?;
+    for value in serialize_layout(node) {
+        writeln!(file, "{}", serde_json::to_string_pretty(&value)?)?;
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_node(node: &Node, id: usize) -> &Node {
+        fn find(node: &Node, id: usize) -> Option<&Node> {
+            if node.id == id {
+                return Some(node);
+            }
+            get_child_iter(node).find_map(|child| find(child, id))
+        }
+        find(node, id).expect("no such node in fixture")
+    }
+
+    fn read_tree() -> Node {
+        serde_json::from_str(include_str!("../tests/fixtures/fullscreen_output.json"))
+            .expect("couldn't parse fixture")
+    }
+
+    #[test]
+    fn windows_swallow_their_class_and_instance_only() {
+        let tree = read_tree();
+        assert_eq!(
+            get_swallows(find_node(&tree, 11)),
+            json!([{ "class": "^firefox$", "instance": "^firefox$" }])
+        );
+    }
+
+    #[test]
+    fn containers_keep_their_layout_and_windows() {
+        let tree = read_tree();
+        let window = |name: &str| {
+            json!({
+                "type": "con",
+                "border": "pixel",
+                "current_border_width": 2,
+                "percent": 1.0,
+                "name": name,
+                "swallows": [{ "class": "^Alacritty$", "instance": "^alacritty$" }],
+            })
+        };
+        assert_eq!(
+            serialize_layout(find_node(&tree, 13)),
+            vec![json!({
+                "type": "con",
+                "border": "normal",
+                "current_border_width": -1,
+                "layout": "splith",
+                "nodes": [window("~"), window("~")],
+            })]
+        );
+    }
+
+    #[test]
+    fn workspaces_are_laid_out_as_their_children() {
+        let tree = read_tree();
+        let layout = serialize_layout(find_node(&tree, 10));
+        assert_eq!(layout.len(), 2);
+        assert_eq!(layout[0]["nodes"].as_array().map(Vec::len), Some(2));
+        assert_eq!(layout[1]["name"], "Mozilla Firefox");
+    }
+}
@@ -1,20 +1,139 @@
 pub mod cli;
+pub mod daemon;
+pub mod desktop_entry;
+pub mod desktop_index;
+pub mod history;
+pub mod i3_config;
+pub mod icon_cache;
+pub mod icon_theme;
+pub mod layout;
+pub mod palette;
+pub mod session;
+pub mod settings;
+pub mod templates;
 pub mod utils;
 
 pub mod external_command {
     use i3_ipc::{reply, I3Stream};
-    use std::io::{self, Write};
+    use serde::Deserialize;
+    use std::collections::HashMap;
+    use std::convert::TryInto;
+    use std::io::{self, Read, Write};
+    use std::os::unix::net::UnixStream;
     use std::process::{Command, Stdio};
 
     pub fn get_tree(con: &mut I3Stream) -> io::Result<reply::Node> {
         con.get_tree()
     }
 
+    pub fn get_config(con: &mut I3Stream) -> io::Result<String> {
+        con.get_config().map(|reply| reply.config)
+    }
+
     pub fn kill(con: &mut I3Stream) -> io::Result<Vec<reply::Success>> {
         con.run_command(&"kill".to_string())
     }
 
-    pub fn prompt_user(prompt: String, config: Option<String>, styles: Option<String>) -> bool {
+    pub fn focus_workspace(con: &mut I3Stream, name: &str) -> io::Result<Vec<reply::Success>> {
+        con.run_command(format!(
+            "workspace \"{}\"",
+            name.replace('\\', "\\\\").replace('"', "\\\"")
+        ))
+    }
+
+    pub fn append_layout(con: &mut I3Stream, path: &str) -> io::Result<Vec<reply::Success>> {
+        con.run_command(format!(
+            "append_layout \"{}\"",
+            path.replace('\\', "\\\\").replace('"', "\\\"")
+        ))
+    }
+
+    /// Reads the scale factor of each active output from sway, whose `GET_OUTPUTS`
+    /// reply has one unlike i3's. `None` means the window manager isn't sway.
+    pub fn get_output_scales() -> io::Result<Option<HashMap<String, f64>>> {
+        const MAGIC: &[u8] = b"i3-ipc";
+        const GET_OUTPUTS: u32 = 3;
+        #[derive(Deserialize)]
+        struct SwayOutput {
+            name: String,
+            scale: Option<f64>,
+        }
+        // sway sets both, the daemon may have been started with only one of them
+        let socket_path = std::env::var_os("SWAYSOCK")
+            .or_else(|| std::env::var_os("I3SOCK"))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no IPC socket"))?;
+        let mut stream = UnixStream::connect(socket_path)?;
+        let mut request = MAGIC.to_vec();
+        request.extend_from_slice(&0u32.to_ne_bytes());
+        request.extend_from_slice(&GET_OUTPUTS.to_ne_bytes());
+        stream.write_all(&request)?;
+        let mut header = [0u8; 14];
+        stream.read_exact(&mut header)?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid IPC reply header",
+            ));
+        }
+        let length = u32::from_ne_bytes(header[6..10].try_into().expect("invalid header"));
+        let mut payload = vec![0u8; length as usize];
+        stream.read_exact(&mut payload)?;
+        let outputs: Vec<SwayOutput> = serde_json::from_slice(&payload)?;
+        if outputs.iter().all(|output| output.scale.is_none()) {
+            return Ok(None);
+        }
+        Ok(Some(
+            outputs
+                .into_iter()
+                // disabled outputs have a scale of -1
+                .filter_map(|output| match output.scale {
+                    Some(scale) if scale > 0.0 => Some((output.name, scale)),
+                    _ => None,
+                })
+                .collect(),
+        ))
+    }
+
+    /// Whether the window manager is sway, to be checked once rather than on every
+    /// prompt since it takes an IPC connection of its own.
+    pub fn is_sway() -> bool {
+        get_output_scales().is_ok_and(|scales| scales.is_some())
+    }
+
+    pub fn get_window_pid(window: usize) -> Option<u32> {
+        const COMMAND: &str = "xprop";
+        let output = Command::new(COMMAND)
+            .args(["-id", window.to_string().as_str(), "_NET_WM_PID"])
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        String::from_utf8(output.stdout)
+            .ok()?
+            .rsplit_once('=')
+            .and_then(|(_, pid)| pid.trim().parse::<u32>().ok())
+    }
+
+    pub fn launch(cmdline: &[String], cwd: Option<&str>) -> io::Result<()> {
+        let (program, args) = cmdline
+            .split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command line"))?;
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        if let Some(cwd) = cwd {
+            command.current_dir(cwd);
+        }
+        command.spawn().map(|_| ())
+    }
+
+    pub fn prompt_user(
+        prompt: String,
+        config: Option<String>,
+        styles: Option<String>,
+    ) -> io::Result<bool> {
         const COMMAND: &str = "rofi";
         const CHOICES: (&str, &str) = ("Yes", "No");
         let mut args = vec!["-dmenu", "-auto-select", "-i", "-p", prompt.as_str()];
@@ -29,195 +148,578 @@ pub mod external_command {
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .spawn()
-            .expect(format!("failed to execute command: {} {}", COMMAND, args.join(" ")).as_str());
-        {
-            let stdin = call.stdin.as_mut().expect("failed to open stdin");
-            stdin
-                .write_all(format!("{}\n{}", CHOICES.0, CHOICES.1).as_bytes())
-                .expect("failed to write to stdin");
-        }
-        let output = call.wait_with_output().expect("failed to read stdout");
-        if let Ok(response) = String::from_utf8(output.stdout) {
-            if response == format!("{}\n", CHOICES.0) {
-                return true;
-            }
+            .map_err(|error| {
+                io::Error::new(
+                    error.kind(),
+                    format!("failed to execute command {}: {}", COMMAND, error),
+                )
+            })?;
+        if let Some(stdin) = call.stdin.as_mut() {
+            stdin.write_all(format!("{}\n{}", CHOICES.0, CHOICES.1).as_bytes())?;
         }
-        false
+        let output = call.wait_with_output()?;
+        Ok(String::from_utf8(output.stdout)
+            .is_ok_and(|response| response == format!("{}\n", CHOICES.0)))
     }
 }
 
 pub mod formatter {
-    use crate::{cli::SmartGapsOption, utils::i3_tree::get_child_iter};
-    use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
-    use i3_ipc::reply::{Node, NodeLayout, NodeType};
-    use ignore::WalkBuilder;
-    use regex::Regex;
-    use serde::Serialize;
-    use std::{
-        collections::HashMap,
-        fs::{self, OpenOptions},
-        io::Write,
-        path::PathBuf,
+    use crate::{
+        cli::{Options, SmartGapsOption},
+        desktop_entry::{DesktopEntry, Locale},
+        desktop_index::DesktopIndex,
+        external_command::get_window_pid,
+        i3_config::I3Config,
+        icon_cache::{CacheEntry, CacheKey, IconCache},
+        icon_theme::IconTheme,
+        palette::{read_palette, Palette},
+        settings::{Escape, FormatterPreset, IconRules, IconThemeSettings, Placement},
+        templates::find_template,
+        utils::{
+            escape,
+            i3_tree::{find_workspace, get_child_iter, get_node_chain, get_windows},
+            proc::read_exe,
+        },
     };
+    use i3_ipc::reply::{FullscreenMode, Node, NodeLayout, NodeType, Rect};
+    use serde::Serialize;
+    use serde_json::Value;
+    use std::{collections::HashMap, io, path::PathBuf};
     use tinytemplate::{format_unescaped, TinyTemplate};
 
+    /// Workspace holding the windows hidden in the scratchpad.
+    const SCRATCHPAD_WORKSPACE: &str = "__i3_scratch";
+
     #[derive(Debug, Serialize)]
-    struct TemplateContext {
-        #[serde(rename(serialize = "container"))]
-        container_rect: NodeRect,
-        nodes: Vec<NodeInfo>,
+    struct TemplateContext<'a> {
+        container: ContainerContext,
+        nodes: NodeList<'a>,
+        groups: &'a [NodeGroup],
+        tree: TreeNode<'a>,
+        minimap: Option<Minimap>,
+        i3: &'a I3Config,
+        palette: &'a Palette,
     }
 
+    /// The windows, or their groups when `group_by_class` is set: groups share the
+    /// fields of windows used by simple templates (`class`, `title`, `icon`...).
     #[derive(Debug, Serialize)]
-    struct NodeRect {
-        top: i32,
-        right: i32,
-        bottom: i32,
-        left: i32,
+    #[serde(untagged)]
+    enum NodeList<'a> {
+        Flat(&'a [NodeInfo]),
+        Grouped(&'a [NodeGroup]),
     }
 
+    /// Windows sharing a class.
     #[derive(Debug, Serialize)]
-    struct NodeInfo {
+    pub struct NodeGroup {
+        pub class: String,
+        /// Title of the window when it's alone, else e.g. "3 windows".
+        pub title: String,
+        pub icon: String,
+        pub icon_path: String,
+        pub count: usize,
+        pub titles: Vec<String>,
+    }
+
+    /// Groups the windows by class, in order of first appearance.
+    pub fn group_nodes_info(nodes_info: &[NodeInfo]) -> Vec<NodeGroup> {
+        let mut groups: Vec<NodeGroup> = Vec::new();
+        for info in nodes_info.iter() {
+            match groups.iter_mut().find(|group| group.class == info.class) {
+                Some(group) => {
+                    group.count += 1;
+                    group.titles.push(info.title.clone());
+                    group.title = format!("{} windows", group.count);
+                }
+                None => groups.push(NodeGroup {
+                    class: info.class.clone(),
+                    title: info.title.clone(),
+                    icon: info.icon.clone(),
+                    icon_path: info.icon_path.clone(),
+                    count: 1,
+                    titles: vec![info.title.clone()],
+                }),
+            }
+        }
+        groups
+    }
+
+    /// Groups named in the prompt, the others being counted.
+    const MAX_PROMPT_GROUPS: usize = 3;
+
+    /// e.g. "Close 12 Alacritty, 2 Firefox, Code and 3 more"
+    fn get_prompt(groups: &[NodeGroup]) -> String {
+        if groups.is_empty() {
+            return String::from("Close node");
+        }
+        let names = groups
+            .iter()
+            .take(MAX_PROMPT_GROUPS)
+            .map(|group| match group.count {
+                1 => group.class.clone(),
+                count => format!("{} {}", count, group.class),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        match groups.len().saturating_sub(MAX_PROMPT_GROUPS) {
+            0 => format!("Close {}", names),
+            more => format!("Close {} and {} more", names, more),
+        }
+    }
+
+    /// Windows of the target's workspace, for drawing a scaled preview of its layout.
+    #[derive(Debug, Serialize)]
+    struct Minimap {
+        workspace: String,
+        width: i32,
+        height: i32,
+        windows: Vec<MinimapWindow>,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct MinimapWindow {
+        con_id: usize,
         class: String,
         title: String,
-        icon: String,
-    }
-
-    fn get_nodes_info(node: &Node, cache_file_path: Option<PathBuf>) -> Vec<NodeInfo> {
-        fn get_icon_by_class(
-            class: &String,
-            cache_path: &Option<PathBuf>,
-            icon_map: &mut HashMap<String, String>,
-        ) -> String {
-            fn get_icon_by_class_from_cache(
-                class: &String,
-                cache_path: &Option<PathBuf>,
-            ) -> Option<String> {
-                if let Some(path) = cache_path {
-                    match fs::read_to_string(path) {
-                        Ok(text) => text.lines().find_map(|line| {
-                            if line.starts_with(class) {
-                                line.rsplit_once("=").map(|splits| splits.1.into())
-                            } else {
-                                None
-                            }
-                        }),
-                        Err(_) => None,
+        floating: bool,
+        focused: bool,
+        /// Whether the window is about to be killed.
+        targeted: bool,
+        /// Geometry in percentages of the workspace.
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    }
+
+    impl Minimap {
+        fn build(node: &Node, tree: &Node, nodes_info: &[NodeInfo]) -> Option<Self> {
+            let workspace = find_workspace(node, tree)?;
+            let to_minimap_window = |window: &Node, floating: bool| {
+                let properties = window.window_properties.as_ref();
+                MinimapWindow {
+                    con_id: window.id,
+                    class: properties
+                        .and_then(|properties| properties.class.clone())
+                        .unwrap_or_default(),
+                    title: properties
+                        .and_then(|properties| properties.title.clone())
+                        .unwrap_or_default(),
+                    floating,
+                    focused: window.focused,
+                    targeted: nodes_info.iter().any(|info| info.id == window.id),
+                    x: to_percent(
+                        window.rect.x as i32 - workspace.rect.x as i32,
+                        workspace.rect.width as i32,
+                    ),
+                    y: to_percent(
+                        window.rect.y as i32 - workspace.rect.y as i32,
+                        workspace.rect.height as i32,
+                    ),
+                    width: to_percent(window.rect.width as i32, workspace.rect.width as i32),
+                    height: to_percent(window.rect.height as i32, workspace.rect.height as i32),
+                }
+            };
+            let tiling_windows = workspace
+                .nodes
+                .iter()
+                .flat_map(get_windows)
+                .map(|window| to_minimap_window(window, false));
+            let floating_windows = workspace
+                .floating_nodes
+                .iter()
+                .flat_map(get_windows)
+                .map(|window| to_minimap_window(window, true));
+            Some(Minimap {
+                workspace: workspace.name.clone().unwrap_or_default(),
+                width: workspace.rect.width as i32,
+                height: workspace.rect.height as i32,
+                windows: tiling_windows.chain(floating_windows).collect(),
+            })
+        }
+    }
+
+    /// Node of the `tree` template variable, which mirrors the target subtree.
+    #[derive(Debug, Serialize)]
+    struct TreeNode<'a> {
+        con_id: usize,
+        node_type: String,
+        layout: String,
+        orientation: String,
+        /// Share of the parent, in the direction of its layout.
+        percent: Option<f64>,
+        /// Info of the window held by the node, if any.
+        window: Option<&'a NodeInfo>,
+        children: Vec<TreeNode<'a>>,
+        floating_children: Vec<TreeNode<'a>>,
+    }
+
+    impl<'a> TreeNode<'a> {
+        fn build(node: &Node, nodes_info: &'a [NodeInfo]) -> Self {
+            let build_children = |nodes: &[Node]| {
+                nodes
+                    .iter()
+                    .map(|node| TreeNode::build(node, nodes_info))
+                    .collect()
+            };
+            TreeNode {
+                con_id: node.id,
+                node_type: get_ipc_name(&node.node_type),
+                layout: get_ipc_name(&node.layout),
+                orientation: get_ipc_name(&node.orientation),
+                percent: node.percent,
+                window: nodes_info.iter().find(|info| info.id == node.id),
+                children: build_children(&node.nodes),
+                floating_children: build_children(&node.floating_nodes),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    pub struct NodeRect {
+        pub top: i32,
+        pub right: i32,
+        pub bottom: i32,
+        pub left: i32,
+    }
+
+    impl From<&Rect> for NodeRect {
+        fn from(rect: &Rect) -> Self {
+            NodeRect {
+                top: rect.y as i32,
+                right: (rect.x + rect.width) as i32,
+                bottom: (rect.y + rect.height) as i32,
+                left: rect.x as i32,
+            }
+        }
+    }
+
+    impl NodeRect {
+        fn width(&self) -> i32 {
+            self.right - self.left
+        }
+
+        fn scale(&self, factor: f64) -> NodeRect {
+            let scale = |value: i32| (value as f64 * factor).round() as i32;
+            NodeRect {
+                top: scale(self.top),
+                right: scale(self.right),
+                bottom: scale(self.bottom),
+                left: scale(self.left),
+            }
+        }
+
+        fn height(&self) -> i32 {
+            self.bottom - self.top
+        }
+    }
+
+    fn to_percent(value: i32, total: i32) -> f64 {
+        if total == 0 {
+            0.0
+        } else {
+            // rounded to keep the rendered styles readable
+            (value as f64 * 10000.0 / total as f64).round() / 100.0
+        }
+    }
+
+    /// A rect along with the values templates would otherwise compute with `calc()`.
+    #[derive(Debug, Serialize)]
+    struct LayoutRect {
+        #[serde(flatten)]
+        rect: NodeRect,
+        width: i32,
+        height: i32,
+        center_x: i32,
+        center_y: i32,
+    }
+
+    impl From<NodeRect> for LayoutRect {
+        fn from(rect: NodeRect) -> Self {
+            LayoutRect {
+                rect,
+                width: rect.width(),
+                height: rect.height(),
+                center_x: (rect.left + rect.right) / 2,
+                center_y: (rect.top + rect.bottom) / 2,
+            }
+        }
+    }
+
+    /// Position and size in percentages of the output.
+    #[derive(Debug, Serialize)]
+    struct PercentRect {
+        top: f64,
+        right: f64,
+        bottom: f64,
+        left: f64,
+        width: f64,
+        height: f64,
+    }
+
+    /// The `container` template variable. Its rects are in logical pixels, the unit of
+    /// rofi once its `dpi` matches the output scale.
+    #[derive(Debug, Serialize)]
+    struct ContainerContext {
+        #[serde(flatten)]
+        layout: LayoutRect,
+        /// The container in physical pixels.
+        physical: LayoutRect,
+        /// Scale factor of the output of the container.
+        scale: f64,
+        /// Relative to the output, absent if the container isn't on one.
+        output_percent: Option<PercentRect>,
+        /// Whether the minimum prompt size fits inside the container.
+        fits: bool,
+        /// The container when the prompt fits, else a rect of the minimum prompt size
+        /// centered on the output.
+        placement: LayoutRect,
+    }
+
+    impl ContainerContext {
+        fn new(
+            rect: NodeRect,
+            output_rect: Option<NodeRect>,
+            scale: f64,
+            min_width: i32,
+            min_height: i32,
+        ) -> Self {
+            let fits = rect.width() >= min_width && rect.height() >= min_height;
+            let placement = match output_rect {
+                Some(output_rect) if !fits => {
+                    let layout = LayoutRect::from(output_rect);
+                    NodeRect {
+                        top: layout.center_y - min_height / 2,
+                        right: layout.center_x + min_width - min_width / 2,
+                        bottom: layout.center_y + min_height - min_height / 2,
+                        left: layout.center_x - min_width / 2,
                     }
-                } else {
-                    None
                 }
+                _ => rect,
+            };
+            ContainerContext {
+                layout: LayoutRect::from(rect),
+                physical: LayoutRect::from(rect.scale(scale)),
+                scale,
+                output_percent: output_rect.map(|output_rect| {
+                    let (width, height) = (output_rect.width(), output_rect.height());
+                    PercentRect {
+                        top: to_percent(rect.top - output_rect.top, height),
+                        right: to_percent(output_rect.right - rect.right, width),
+                        bottom: to_percent(output_rect.bottom - rect.bottom, height),
+                        left: to_percent(rect.left - output_rect.left, width),
+                        width: to_percent(rect.width(), width),
+                        height: to_percent(rect.height(), height),
+                    }
+                }),
+                fits,
+                placement: LayoutRect::from(placement),
             }
-            fn get_desktop_file_by_class(class: &String) -> Option<String> {
-                let matcher = SkimMatcherV2::ignore_case(SkimMatcherV2::default());
-                let re_desktop = Regex::new(r".*\.desktop$").unwrap();
-                let mut matches_desktop: Vec<(String, i64)> =
-                    WalkBuilder::new("/usr/share/applications")
-                        .build()
-                        .filter_map(|entry| match entry {
-                            Ok(entry) => {
-                                if entry.path().is_file() {
-                                    if let Some(path) = entry.path().to_str() {
-                                        if re_desktop.is_match(path) {
-                                            if let Ok(text) = fs::read_to_string(path) {
-                                                text.lines().find_map(|line| {
-                                                    if line.starts_with("Name=") {
-                                                        if let Some(name_value) = line
-                                                            .split("=")
-                                                            .collect::<Vec<&str>>()
-                                                            .get(1)
-                                                        {
-                                                            if let Some(score) = matcher
-                                                                .fuzzy_match(
-                                                                    &name_value,
-                                                                    class.as_str(),
-                                                                )
-                                                            {
-                                                                Some((path.into(), score))
-                                                            } else {
-                                                                None
-                                                            }
-                                                        } else {
-                                                            None
-                                                        }
-                                                    } else {
-                                                        None
-                                                    }
-                                                })
-                                            } else {
-                                                None
-                                            }
-                                        } else {
-                                            None
-                                        }
-                                    } else {
-                                        None
-                                    }
-                                } else {
-                                    None
-                                }
-                            }
-                            Err(_) => None,
-                        })
-                        .collect();
-                matches_desktop.sort_by(|a, b| b.1.cmp(&a.1));
-                matches_desktop.get(0).map(|(file, _)| file.into())
+        }
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct NodeInfo {
+        #[serde(rename(serialize = "con_id"))]
+        pub id: usize,
+        /// X11 window id.
+        pub window: Option<usize>,
+        pub class: String,
+        pub instance: String,
+        pub title: String,
+        pub window_role: String,
+        pub window_type: String,
+        pub icon: String,
+        /// File of the icon in the icon theme, empty if it couldn't be found.
+        pub icon_path: String,
+        pub app: AppInfo,
+        pub marks: Vec<String>,
+        pub urgent: bool,
+        pub focused: bool,
+        pub floating: bool,
+        pub fullscreen: bool,
+        pub sticky: bool,
+        /// Hidden in the scratchpad.
+        pub scratchpad: bool,
+        pub workspace: String,
+        pub workspace_num: Option<i32>,
+        pub output: String,
+        pub rect: NodeGeometry,
+        pub deco_rect: NodeGeometry,
+    }
+
+    /// Absolute position and size, in pixels.
+    #[derive(Debug, Serialize)]
+    pub struct NodeGeometry {
+        pub x: i32,
+        pub y: i32,
+        pub width: i32,
+        pub height: i32,
+    }
+
+    impl From<&Rect> for NodeGeometry {
+        fn from(rect: &Rect) -> Self {
+            NodeGeometry {
+                x: rect.x as i32,
+                y: rect.y as i32,
+                width: rect.width as i32,
+                height: rect.height as i32,
             }
-            if let Some(icon_name) = icon_map.get(class) {
-                icon_name.into()
-            } else {
-                let icon_name = match get_icon_by_class_from_cache(class, cache_path) {
-                    Some(icon_name) => icon_name,
-                    None => {
-                        let default_icon_name = class.clone();
-                        let new_icon_name = if let Some(file) = get_desktop_file_by_class(class) {
-                            if let Ok(text) = fs::read_to_string(file) {
-                                if let Some(icon_line) =
-                                    text.lines().find(|line| line.starts_with("Icon="))
-                                {
-                                    if let Some(icon_name) =
-                                        icon_line.split("=").collect::<Vec<&str>>().get(1)
-                                    {
-                                        icon_name.trim().to_string()
-                                    } else {
-                                        default_icon_name
-                                    }
-                                } else {
-                                    default_icon_name
-                                }
-                            } else {
-                                default_icon_name
-                            }
-                        } else {
-                            default_icon_name
-                        };
-                        if let Some(path) = cache_path {
-                            match OpenOptions::new()
-                                .create(true)
-                                .write(true)
-                                .append(true)
-                                .open(path)
-                            {
-                                Ok(mut file) => {
-                                    if let Err(_) = writeln!(file, "{}={}", class, new_icon_name) {
-                                        eprintln!("couldn't write icon entry to cache");
-                                    }
-                                }
-                                Err(_) => eprintln!("couldn't open cache file to write"),
-                            }
-                        }
-                        new_icon_name
+        }
+    }
+
+    /// What a node inherits from its ancestors.
+    #[derive(Debug, Clone, Copy, Default)]
+    struct Ancestry<'a> {
+        workspace: Option<&'a Node>,
+        floating: bool,
+    }
+
+    impl<'a> Ancestry<'a> {
+        fn with(self, node: &'a Node) -> Self {
+            Ancestry {
+                workspace: match node.node_type {
+                    NodeType::Workspace => Some(node),
+                    _ => self.workspace,
+                },
+                floating: self.floating || node.node_type == NodeType::FloatingCon,
+            }
+        }
+    }
+
+    /// Name of a variant in the i3 IPC replies (e.g. `dropdown_menu`), empty if it
+    /// isn't serialized as a string.
+    fn get_ipc_name<T: Serialize>(value: &T) -> String {
+        match serde_json::to_value(value) {
+            Ok(Value::String(name)) => name,
+            _ => String::new(),
+        }
+    }
+
+    /// Localized keys of the desktop entry matching the window, empty when there's none.
+    #[derive(Debug, Default, Serialize)]
+    pub struct AppInfo {
+        pub name: String,
+        pub generic_name: String,
+        pub comment: String,
+        pub exec: String,
+    }
+
+    /// Icon of a window and the desktop entry it came from.
+    #[derive(Debug, Clone)]
+    pub struct ResolvedIcon {
+        pub icon: String,
+        pub desktop_file: Option<PathBuf>,
+    }
+
+    /// Icon lookup state, kept warm across requests by the daemon.
+    #[derive(Debug)]
+    pub struct IconState {
+        pub icon_map: HashMap<CacheKey, ResolvedIcon>,
+        pub applications_dirs: Vec<PathBuf>,
+        /// Built on the first window missing from the cache.
+        pub desktop_index: Option<DesktopIndex>,
+        pub cache: Option<IconCache>,
+        /// User overrides, consulted before the cache.
+        pub icon_rules: IconRules,
+        pub icon_theme_settings: IconThemeSettings,
+        /// Loaded on the first icon resolved to a file.
+        pub icon_theme: Option<IconTheme>,
+    }
+
+    impl IconState {
+        pub fn new(options: &Options) -> Self {
+            IconState {
+                icon_map: HashMap::new(),
+                applications_dirs: options.applications_dirs.clone(),
+                desktop_index: None,
+                cache: options.cache_file_path.clone().map(IconCache::load),
+                icon_rules: options.settings.icons.clone(),
+                icon_theme_settings: options.settings.icon_theme.clone(),
+                icon_theme: None,
+            }
+        }
+
+        pub fn desktop_index(&mut self) -> &DesktopIndex {
+            let applications_dirs = &self.applications_dirs;
+            self.desktop_index
+                .get_or_insert_with(|| DesktopIndex::build(applications_dirs))
+        }
+
+        pub fn icon_theme(&mut self) -> &mut IconTheme {
+            let settings = &self.icon_theme_settings;
+            self.icon_theme
+                .get_or_insert_with(|| IconTheme::from_settings(settings))
+        }
+    }
+
+    /// Describes every window of the node, the tree giving their workspace and
+    /// whether they float.
+    pub fn get_nodes_info(node: &Node, tree: &Node, icon_state: &mut IconState) -> Vec<NodeInfo> {
+        fn get_icon(
+            key: &CacheKey,
+            window: Option<usize>,
+            icon_state: &mut IconState,
+        ) -> ResolvedIcon {
+            if let Some(resolved) = icon_state.icon_map.get(key) {
+                return resolved.clone();
+            }
+            let cached = icon_state
+                .cache
+                .as_ref()
+                .and_then(|cache| {
+                    cache
+                        .get(key)
+                        .or_else(|| cache.get(&CacheKey::from_class(&key.class)))
+                })
+                .map(|entry| ResolvedIcon {
+                    icon: entry.icon.clone().unwrap_or_else(|| key.class.clone()),
+                    desktop_file: entry.desktop_file.clone(),
+                });
+            let resolved = match cached {
+                Some(resolved) => resolved,
+                None => {
+                    let (icon, desktop_file) = match icon_state.desktop_index().find_by_window(
+                        &key.class,
+                        key.instance.as_deref(),
+                        || window.and_then(get_window_pid).and_then(read_exe),
+                    ) {
+                        Some((indexed, _)) => (indexed.icon.clone(), Some(indexed.path.clone())),
+                        None => (None, None),
+                    };
+                    if let Some(cache) = icon_state.cache.as_mut() {
+                        cache.insert(CacheEntry::new(
+                            key.clone(),
+                            icon.clone(),
+                            desktop_file.clone(),
+                        ));
                     }
-                };
-                icon_map.insert(class.clone(), icon_name.clone());
-                icon_name
+                    ResolvedIcon {
+                        icon: icon.unwrap_or_else(|| key.class.clone()),
+                        desktop_file,
+                    }
+                }
+            };
+            icon_state.icon_map.insert(key.clone(), resolved.clone());
+            resolved
+        }
+        fn get_app_info(entry: &DesktopEntry, locale: Option<&Locale>) -> AppInfo {
+            let get = |key| entry.get_localized(key, locale).unwrap_or_default();
+            AppInfo {
+                name: get("Name"),
+                generic_name: get("GenericName"),
+                comment: get("Comment"),
+                exec: entry.exec().unwrap_or_default(),
             }
         }
         fn build_nodes_info(
             node: &Node,
-            cache_path: &Option<PathBuf>,
-            icon_map: &mut HashMap<String, String>,
+            ancestry: Ancestry,
+            icon_state: &mut IconState,
+            locale: Option<&Locale>,
         ) -> Vec<NodeInfo> {
+            let ancestry = ancestry.with(node);
             let mut nodes_info: Vec<NodeInfo> = Vec::new();
             if let Some(window_properties) = &node.window_properties {
                 let class = window_properties
@@ -230,23 +732,102 @@ pub mod formatter {
                     .as_ref()
                     .unwrap_or(&String::from("Unknown"))
                     .clone();
-                let icon = get_icon_by_class(&class, cache_path, icon_map);
-                nodes_info.push(NodeInfo { class, title, icon });
+                let key = CacheKey {
+                    class: class.clone(),
+                    instance: window_properties.instance.clone(),
+                };
+                let resolved = match icon_state.icon_rules.find(
+                    &class,
+                    window_properties.instance.as_deref(),
+                    &title,
+                ) {
+                    Some(rule) => ResolvedIcon {
+                        icon: rule.icon.clone(),
+                        desktop_file: None,
+                    },
+                    None => get_icon(&key, node.window, icon_state),
+                };
+                let app = resolved
+                    .desktop_file
+                    .and_then(|path| DesktopEntry::read(path).ok())
+                    .map(|entry| get_app_info(&entry, locale))
+                    .unwrap_or_default();
+                let icon_path = icon_state
+                    .icon_theme()
+                    .find_icon(&resolved.icon)
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let workspace = ancestry.workspace;
+                nodes_info.push(NodeInfo {
+                    id: node.id,
+                    window: node.window,
+                    class,
+                    instance: window_properties.instance.clone().unwrap_or_default(),
+                    title,
+                    window_role: window_properties.window_role.clone().unwrap_or_default(),
+                    window_type: node
+                        .window_type
+                        .as_ref()
+                        .map(get_ipc_name)
+                        .unwrap_or_default(),
+                    icon: resolved.icon,
+                    icon_path,
+                    app,
+                    marks: node.marks.clone(),
+                    urgent: node.urgent,
+                    focused: node.focused,
+                    floating: ancestry.floating,
+                    fullscreen: node.fullscreen_mode != FullscreenMode::None,
+                    sticky: node.sticky,
+                    scratchpad: workspace
+                        .and_then(|workspace| workspace.name.as_deref())
+                        .is_some_and(|name| name == SCRATCHPAD_WORKSPACE),
+                    workspace: workspace
+                        .and_then(|workspace| workspace.name.clone())
+                        .unwrap_or_default(),
+                    workspace_num: workspace.and_then(|workspace| workspace.num),
+                    output: node.output.clone().unwrap_or_default(),
+                    rect: NodeGeometry::from(&node.rect),
+                    deco_rect: NodeGeometry::from(&node.deco_rect),
+                });
             }
             get_child_iter(node).for_each(|node| {
-                nodes_info.append(build_nodes_info(node, cache_path, icon_map).as_mut())
+                nodes_info.append(build_nodes_info(node, ancestry, icon_state, locale).as_mut())
             });
             nodes_info
         }
-        let mut icon_map: HashMap<String, String> = HashMap::new();
-        build_nodes_info(node, &cache_file_path, &mut icon_map)
+        // icons set, removed or rebuilt by the cache subcommands replace the ones
+        // resolved so far
+        if icon_state
+            .cache
+            .as_mut()
+            .is_some_and(|cache| cache.refresh())
+        {
+            icon_state.icon_map.clear();
+        }
+        let locale = Locale::from_env();
+        let ancestry = get_node_chain(node, tree)
+            .map(|chain| {
+                chain[..chain.len() - 1]
+                    .iter()
+                    .fold(Ancestry::default(), |ancestry, node| ancestry.with(node))
+            })
+            .unwrap_or_default();
+        let nodes_info = build_nodes_info(node, ancestry, icon_state, locale.as_ref());
+        if let Some(cache) = icon_state.cache.as_mut() {
+            if let Err(error) = cache.save() {
+                eprintln!("couldn't write icon cache: {}", error);
+            }
+        }
+        nodes_info
     }
 
-    fn find_inherited_rect(
+    pub fn find_inherited_rect(
         target: &Node,
         node: &Node,
         global_smart_gaps: SmartGapsOption,
         global_outer_gap: Option<i32>,
+        placement: Placement,
     ) -> NodeRect {
         fn get_node_with_childs(node: &Node) -> Option<&Node> {
             if node.nodes.len() > 1 {
@@ -352,82 +933,296 @@ pub mod formatter {
                 left: x,
             }
         }
-        fn get_node_chain<'a>(target: &Node, node: &'a Node) -> Option<Vec<&'a Node>> {
-            if node.id == target.id {
-                return Some(vec![node]);
+        /// The title bar is drawn by the parent, relative to which `deco_rect` is given.
+        /// `window_rect` already accounts for the border width, `hide_edge_borders`
+        /// and `smart_borders`.
+        fn get_target_rect(target: &Node, parent: Option<&Node>, placement: Placement) -> NodeRect {
+            let rect = NodeRect::from(&target.rect);
+            let deco_rect = parent
+                .filter(|_| target.deco_rect.width > 0 && target.deco_rect.height > 0)
+                .map(|parent| NodeRect {
+                    top: (parent.rect.y + target.deco_rect.y) as i32,
+                    right: (parent.rect.x + target.deco_rect.x + target.deco_rect.width) as i32,
+                    bottom: (parent.rect.y + target.deco_rect.y + target.deco_rect.height) as i32,
+                    left: (parent.rect.x + target.deco_rect.x) as i32,
+                });
+            match placement {
+                Placement::Frame => match deco_rect {
+                    Some(deco_rect) => NodeRect {
+                        top: rect.top.min(deco_rect.top),
+                        right: rect.right.max(deco_rect.right),
+                        bottom: rect.bottom.max(deco_rect.bottom),
+                        left: rect.left.min(deco_rect.left),
+                    },
+                    None => rect,
+                },
+                Placement::Content => {
+                    let window_rect = &target.window_rect;
+                    if window_rect.width > 0 && window_rect.height > 0 {
+                        NodeRect {
+                            top: rect.top + window_rect.y as i32,
+                            right: rect.left + (window_rect.x + window_rect.width) as i32,
+                            bottom: rect.top + (window_rect.y + window_rect.height) as i32,
+                            left: rect.left + window_rect.x as i32,
+                        }
+                    } else {
+                        let border = target.current_border_width.max(0);
+                        NodeRect {
+                            top: rect.top + border,
+                            right: rect.right - border,
+                            bottom: rect.bottom - border,
+                            left: rect.left + border,
+                        }
+                    }
+                }
+                Placement::TitleBar => deco_rect.unwrap_or(rect),
             }
-            get_child_iter(node).find_map(|n| match get_node_chain(target, n) {
-                Some(chain) => Some(
-                    vec![node]
-                        .iter()
-                        .chain(chain.iter())
-                        .map(|n| *n)
-                        .collect::<Vec<&'a Node>>(),
-                ),
-                None => None,
-            })
         }
-        let node_rect_default = get_node_rect(target, false, global_outer_gap);
-        match get_node_chain(target, node) {
-            Some(chain) => {
-                let with_gaps =
-                    match can_workspace_of_node_have_gaps(target, &chain, global_smart_gaps) {
-                        Some(can_have_gaps) => can_have_gaps,
-                        None => false,
-                    };
-                chain
-                    .iter()
-                    .rev()
-                    .map(|n| get_node_rect(n, with_gaps, global_outer_gap))
-                    .reduce(|a, b| NodeRect {
-                        top: a.top.max(b.top),
-                        right: a.right.min(b.right),
-                        bottom: a.bottom.min(b.bottom),
-                        left: a.left.max(b.left),
-                    })
-                    .unwrap_or(node_rect_default)
+        fn intersect(a: NodeRect, b: NodeRect) -> NodeRect {
+            NodeRect {
+                top: a.top.max(b.top),
+                right: a.right.min(b.right),
+                bottom: a.bottom.min(b.bottom),
+                left: a.left.max(b.left),
+            }
+        }
+        let chain = match get_node_chain(target, node) {
+            Some(chain) => chain,
+            None => return get_target_rect(target, None, placement),
+        };
+        let parent = chain.len().checked_sub(2).map(|index| chain[index]);
+        let target_rect = get_target_rect(target, parent, placement);
+        let last = chain.len() - 1;
+        // a fullscreen container covers its output, or every output, whatever the
+        // gaps of its ancestors
+        if let Some(index) = chain
+            .iter()
+            .rposition(|n| n.fullscreen_mode != FullscreenMode::None)
+        {
+            let fullscreen_rect = match chain[index].fullscreen_mode {
+                FullscreenMode::Global => chain.first().filter(|n| n.node_type == NodeType::Root),
+                _ => chain.iter().find(|n| n.node_type == NodeType::Output),
+            }
+            .map(|area| NodeRect::from(&area.rect))
+            .unwrap_or_else(|| NodeRect::from(&chain[index].rect));
+            if index == last {
+                return fullscreen_rect;
+            }
+            return chain[index + 1..last]
+                .iter()
+                .map(|n| NodeRect::from(&n.rect))
+                .fold(intersect(target_rect, fullscreen_rect), intersect);
+        }
+        // a floating window is drawn over its workspace rather than clipped by it, and
+        // its frame is the one of the floating container wrapping it
+        if let Some(index) = chain
+            .iter()
+            .rposition(|n| n.node_type == NodeType::FloatingCon)
+        {
+            let frame_rect = NodeRect::from(&chain[index].rect);
+            if index == last {
+                return intersect(target_rect, frame_rect);
+            }
+            if index + 1 == last && matches!(placement, Placement::Frame) {
+                return frame_rect;
             }
-            None => node_rect_default,
+            return chain[index + 1..last]
+                .iter()
+                .map(|n| NodeRect::from(&n.rect))
+                .fold(intersect(target_rect, frame_rect), intersect);
         }
+        let with_gaps =
+            can_workspace_of_node_have_gaps(target, &chain, global_smart_gaps).unwrap_or(false);
+        chain
+            .iter()
+            .rev()
+            .skip(1)
+            .map(|n| get_node_rect(n, with_gaps, global_outer_gap))
+            .fold(
+                // the gaps of a focused workspace are its own
+                intersect(
+                    target_rect,
+                    get_node_rect(target, with_gaps, global_outer_gap),
+                ),
+                intersect,
+            )
+    }
+
+    fn format_rasi_string(value: &Value, output: &mut String) -> tinytemplate::error::Result<()> {
+        let mut unescaped = String::new();
+        format_unescaped(value, &mut unescaped)?;
+        output.push_str(&escape::rasi_string(&unescaped));
+        Ok(())
+    }
+
+    /// Registers a formatter escaping the value as formatted by `format_unescaped`.
+    fn add_escaping_formatter<'t>(
+        tt: &mut TinyTemplate<'t>,
+        name: &'t str,
+        escape_value: impl Fn(&str) -> String + 'static,
+    ) {
+        tt.add_formatter(name, move |value, output| {
+            let mut unescaped = String::new();
+            format_unescaped(value, &mut unescaped)?;
+            output.push_str(&escape_value(&unescaped));
+            Ok(())
+        });
     }
 
-    fn get_rofi_styles(context: TemplateContext, template: String) -> String {
+    /// Values are escaped for rasi strings unless formatted otherwise, e.g.
+    /// `{node.title | pango_markup}` or `{node.title | raw}`. Presets of the settings
+    /// are registered last and can shadow the built-in formatters.
+    fn get_rofi_styles<'a>(
+        context: TemplateContext,
+        template: &'a str,
+        formatters: &'a HashMap<String, FormatterPreset>,
+    ) -> io::Result<String> {
         const TEMPLATE_NAME: &str = "main";
         let mut tt = TinyTemplate::new();
-        tt.set_default_formatter(&format_unescaped);
-        if let Err(e) = tt.add_template(TEMPLATE_NAME, &template) {
-            panic!("couldn't register template: {}", e);
-        }
-        match tt.render(TEMPLATE_NAME, &context) {
-            Ok(rendered) => rendered,
-            Err(e) => {
-                panic!("couldn't render template: {}", e);
-            }
+        tt.set_default_formatter(&format_rasi_string);
+        for (name, escape) in [
+            ("raw", Escape::Raw),
+            ("rasi_string", Escape::RasiString),
+            ("pango_markup", Escape::PangoMarkup),
+            ("shell", Escape::Shell),
+        ] {
+            add_escaping_formatter(&mut tt, name, move |value| escape.apply(value));
+        }
+        add_escaping_formatter(&mut tt, "upper", |value| {
+            Escape::RasiString.apply(&value.to_uppercase())
+        });
+        add_escaping_formatter(&mut tt, "lower", |value| {
+            Escape::RasiString.apply(&value.to_lowercase())
+        });
+        for (name, preset) in formatters.iter() {
+            let preset = preset.clone();
+            add_escaping_formatter(&mut tt, name, move |value| preset.apply(value));
         }
+        tt.add_template(TEMPLATE_NAME, template).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("couldn't register template: {}", e),
+            )
+        })?;
+        tt.render(TEMPLATE_NAME, &context).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("couldn't render template: {}", e),
+            )
+        })
     }
 
     pub fn get_prompt_and_styles(
         node: &Node,
         tree: &Node,
-        template: Option<PathBuf>,
-        global_smart_gaps: SmartGapsOption,
-        global_outer_gap: Option<i32>,
-        cache_file_path: Option<PathBuf>,
-    ) -> (String, Option<String>) {
-        let nodes_info = get_nodes_info(node, cache_file_path);
-        let prompt = format!("Close node{}", if nodes_info.len() > 1 { "s" } else { "" });
-        let container_rect = find_inherited_rect(node, tree, global_smart_gaps, global_outer_gap);
+        nodes_info: &[NodeInfo],
+        options: &Options,
+        output_scales: Option<&HashMap<String, f64>>,
+        i3_config: &I3Config,
+    ) -> io::Result<(String, Option<String>)> {
+        let settings = &options.settings;
+        let groups = group_nodes_info(nodes_info);
+        let prompt = get_prompt(&groups);
+        let container_rect = find_inherited_rect(
+            node,
+            tree,
+            options.global_smart_gaps,
+            options.global_outer_gap,
+            settings.template.placement,
+        );
+        let output = get_node_chain(node, tree)
+            .and_then(|chain| chain.into_iter().find(|n| n.node_type == NodeType::Output));
+        let output_name = output.and_then(|output| output.name.as_deref());
+        let scale = output_name
+            .and_then(|name| {
+                settings
+                    .scale
+                    .outputs
+                    .get(name)
+                    .or_else(|| output_scales.and_then(|scales| scales.get(name)))
+            })
+            .copied()
+            .unwrap_or(settings.scale.default);
+        // sway lays out in logical pixels, i3 in physical ones
+        let to_logical = match output_scales {
+            Some(_) => 1.0,
+            None => 1.0 / scale,
+        };
+        let output_rect = output.map(|output| NodeRect::from(&output.rect).scale(to_logical));
+        let palette = read_palette(&settings.palette);
         let context = TemplateContext {
-            nodes: nodes_info,
-            container_rect,
+            nodes: if settings.template.group_by_class {
+                NodeList::Grouped(&groups)
+            } else {
+                NodeList::Flat(nodes_info)
+            },
+            groups: &groups,
+            tree: TreeNode::build(node, nodes_info),
+            minimap: Minimap::build(node, tree, nodes_info),
+            i3: i3_config,
+            palette: &palette,
+            container: ContainerContext::new(
+                container_rect.scale(to_logical),
+                output_rect,
+                scale,
+                settings.template.min_prompt_width,
+                settings.template.min_prompt_height,
+            ),
         };
-        let styles = match template {
-            Some(path) => {
-                let contents = std::fs::read_to_string(path).expect("couldn't read template file");
-                Some(get_rofi_styles(context, contents))
-            }
-            None => None,
+        let contents = match (&options.rofi_theme_file, &options.template_name) {
+            (Some(path), _) => Some(std::fs::read_to_string(path).map_err(|error| {
+                io::Error::new(
+                    error.kind(),
+                    format!("couldn't read template file {}: {}", path.display(), error),
+                )
+            })?),
+            (None, Some(name)) => find_template(name).map(|template| template.contents()),
+            (None, None) => None,
         };
-        (prompt, styles)
+        let styles = contents
+            .map(|contents| get_rofi_styles(context, &contents, &settings.formatters))
+            .transpose()?;
+        Ok((prompt, styles))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn group(class: &str, count: usize) -> NodeGroup {
+            NodeGroup {
+                class: class.to_owned(),
+                title: format!("{} windows", count),
+                icon: String::new(),
+                icon_path: String::new(),
+                count,
+                titles: Vec::new(),
+            }
+        }
+
+        #[test]
+        fn prompt_names_every_group_up_to_the_limit() {
+            assert_eq!(get_prompt(&[]), "Close node");
+            assert_eq!(
+                get_prompt(&[
+                    group("Alacritty", 12),
+                    group("Firefox", 2),
+                    group("Code", 1)
+                ]),
+                "Close 12 Alacritty, 2 Firefox, Code"
+            );
+        }
+
+        #[test]
+        fn prompt_counts_the_groups_past_the_limit() {
+            let groups: Vec<NodeGroup> = ["Alacritty", "Firefox", "Code", "Gimp", "mpv", "Zathura"]
+                .iter()
+                .map(|class| group(class, 1))
+                .collect();
+            assert_eq!(
+                get_prompt(&groups),
+                "Close Alacritty, Firefox, Code and 3 more"
+            );
+        }
     }
 }
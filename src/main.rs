@@ -1,10 +1,315 @@
-use i3_ipc::{reply::NodeType, Connect, I3};
+use i3_ipc::{Connect, I3};
 use i3_window_killer::{
-    cli::get_options,
-    external_command::{get_tree, kill, prompt_user},
-    formatter::get_prompt_and_styles,
-    utils::{fs::create_parent_dir, i3_tree::find_focused},
+    cli::{get_options, CacheCommand, Command, Options},
+    daemon::{self, send_request, REQUEST_KILL},
+    desktop_index::{get_min_fuzzy_score, DesktopIndex, MatchKind},
+    external_command::{append_layout, focus_workspace, get_tree, is_sway, launch},
+    formatter::{get_nodes_info, IconState},
+    history::{last_undoable, read_entries},
+    icon_cache::{CacheEntry, CacheKey, IconCache},
+    icon_theme::IconTheme,
+    session,
+    templates::TEMPLATES,
+    utils::{fs::create_parent_dir, i3_tree::get_windows},
 };
+use std::{io, sync::Mutex};
+
+fn kill_focused(options: Options) {
+    let mut con = I3::connect().expect("failed to connect");
+    let tree = get_tree(&mut con).expect("failed to send command");
+    if let Err(error) = session::kill_focused(
+        &mut con,
+        &tree,
+        &options,
+        &Mutex::new(IconState::new(&options)),
+        is_sway(),
+    ) {
+        eprintln!("{}", error);
+    }
+}
+
+fn run_client(options: Options) {
+    let outcome = match options.socket_path {
+        Some(ref socket_path) => send_request(socket_path, REQUEST_KILL),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, "no socket")),
+    };
+    match outcome {
+        Ok(Ok(())) => (),
+        Ok(Err(error)) => eprintln!("{}", error),
+        // no daemon to talk to, do the work ourselves
+        Err(_) => kill_focused(options),
+    }
+}
+
+fn list_history(options: Options) {
+    let path = options
+        .history_file_path
+        .expect("couldn't locate history file");
+    let entries = read_entries(&path).unwrap_or_default();
+    for (index, entry) in entries.iter().enumerate() {
+        println!(
+            "{:>3}  [{}] {}: {}  ({})",
+            index + 1,
+            entry.workspace.as_deref().unwrap_or("?"),
+            entry.class,
+            entry.title,
+            entry.cmdline.join(" ")
+        );
+    }
+}
+
+fn reopen(options: Options, index: usize) {
+    let path = options
+        .history_file_path
+        .expect("couldn't locate history file");
+    let entries = read_entries(&path).unwrap_or_default();
+    let entry = match entries.get(index - 1) {
+        Some(entry) => entry,
+        None => {
+            eprintln!("no history entry at index {}", index);
+            return;
+        }
+    };
+    if entry.cmdline.is_empty() {
+        eprintln!(
+            "no command line recorded for {}: {}",
+            entry.class, entry.title
+        );
+        return;
+    }
+    if let Some(ref workspace) = entry.workspace {
+        let mut con = I3::connect().expect("failed to connect");
+        focus_workspace(&mut con, workspace).expect("failed to execute command");
+    }
+    if let Err(error) = launch(&entry.cmdline, entry.cwd.as_deref()) {
+        eprintln!("Couldn't relaunch {}: {}", entry.cmdline.join(" "), error);
+    }
+}
+
+fn undo(options: Options) {
+    let path = options
+        .history_file_path
+        .expect("couldn't locate history file");
+    let entries = last_undoable(read_entries(&path).unwrap_or_default());
+    let layout = match entries.first().and_then(|entry| entry.layout.clone()) {
+        Some(layout) => layout,
+        None => {
+            eprintln!("nothing to undo");
+            return;
+        }
+    };
+    let mut con = I3::connect().expect("failed to connect");
+    if let Some(ref workspace) = entries[0].workspace {
+        focus_workspace(&mut con, workspace).expect("failed to execute command");
+    }
+    let outcomes = append_layout(&mut con, layout.to_str().expect("invalid layout path"))
+        .expect("failed to execute command");
+    for outcome in outcomes.iter().filter(|outcome| !outcome.success) {
+        eprintln!("couldn't restore layout");
+        if let Some(e) = outcome.error.as_ref() {
+            eprintln!("{}", e);
+        }
+    }
+    // windows sharing a process were killed together, so each process is launched once
+    let mut launched_pids: Vec<u32> = Vec::new();
+    for entry in entries.iter() {
+        if let Some(pid) = entry.pid {
+            if launched_pids.contains(&pid) {
+                continue;
+            }
+            launched_pids.push(pid);
+        }
+        if entry.cmdline.is_empty() {
+            eprintln!(
+                "no command line recorded for {}: {}",
+                entry.class, entry.title
+            );
+        } else if let Err(error) = launch(&entry.cmdline, entry.cwd.as_deref()) {
+            eprintln!("Couldn't relaunch {}: {}", entry.cmdline.join(" "), error);
+        }
+    }
+    if let Err(error) = std::fs::remove_file(&layout) {
+        eprintln!("Couldn't remove restored layout: {}", error);
+    }
+}
+
+fn print_cache_entry(entry: &CacheEntry) {
+    println!(
+        "{}{}  ->  {}  ({})",
+        entry.key.class,
+        entry
+            .key
+            .instance
+            .as_ref()
+            .map(|instance| format!(" [{}]", instance))
+            .unwrap_or_default(),
+        entry.icon.as_deref().unwrap_or("-"),
+        match (&entry.desktop_file, entry.is_valid()) {
+            (_, false) => String::from("stale"),
+            (Some(desktop_file), true) => desktop_file.display().to_string(),
+            (None, true) if entry.is_set_by_hand() => String::from("set by hand"),
+            (None, true) => String::from("not found"),
+        }
+    );
+}
+
+fn manage_cache(options: &Options, command: &CacheCommand) {
+    let path = match options.cache_file_path {
+        Some(ref path) => path.clone(),
+        None => {
+            eprintln!("icons cache is disabled");
+            return;
+        }
+    };
+    let mut cache = IconCache::load(path);
+    match command {
+        CacheCommand::List => {
+            let mut entries: Vec<&CacheEntry> = cache.entries().collect();
+            entries.sort_by(|a, b| a.key.cmp(&b.key));
+            entries.into_iter().for_each(print_cache_entry);
+        }
+        CacheCommand::Get(class) => {
+            let mut entries: Vec<&CacheEntry> = cache
+                .entries()
+                .filter(|entry| &entry.key.class == class)
+                .collect();
+            if entries.is_empty() {
+                eprintln!("no cached icon for {}", class);
+            }
+            entries.sort_by(|a, b| a.key.cmp(&b.key));
+            entries.into_iter().for_each(print_cache_entry);
+        }
+        CacheCommand::Set(class, icon) => {
+            cache.remove_class(class);
+            cache.insert(CacheEntry::new(
+                CacheKey::from_class(class),
+                Some(icon.clone()),
+                None,
+            ));
+        }
+        CacheCommand::Remove(class) => {
+            if cache.remove_class(class).is_empty() {
+                eprintln!("no cached icon for {}", class);
+            }
+        }
+        CacheCommand::Prune => {
+            cache.prune().iter().for_each(print_cache_entry);
+        }
+        CacheCommand::Rebuild => {
+            let mut con = I3::connect().expect("failed to connect");
+            let tree = get_tree(&mut con).expect("failed to send command");
+            let windows = get_windows(&tree);
+            // icons set by hand are kept, they take precedence over the resolved ones
+            let stale_keys: Vec<CacheKey> = cache
+                .entries()
+                .filter(|entry| {
+                    !entry.is_set_by_hand()
+                        && windows.iter().any(|window| {
+                            window
+                                .window_properties
+                                .as_ref()
+                                .and_then(|properties| properties.class.as_ref())
+                                == Some(&entry.key.class)
+                        })
+                })
+                .map(|entry| entry.key.clone())
+                .collect();
+            stale_keys.iter().for_each(|key| {
+                cache.remove(key);
+            });
+            let mut icon_state = IconState::new(options);
+            icon_state.cache = Some(cache);
+            get_nodes_info(&tree, &tree, &mut icon_state);
+            cache = icon_state.cache.expect("couldn't get icons cache");
+            println!("resolved the icons of {} windows", windows.len());
+        }
+    }
+    if let Err(error) = cache.save() {
+        eprintln!("Couldn't write icons cache: {}", error);
+    }
+}
+
+fn explain_icon(options: &Options, class: &str, instance: Option<&str>) {
+    // title patterns are tried against an empty title
+    let rule = options.settings.icons.find(class, instance, "");
+    match rule {
+        Some(rule) => println!("settings rule: {}", rule.icon),
+        None => println!("settings rule: none"),
+    }
+    let key = CacheKey {
+        class: class.to_string(),
+        instance: instance.map(|s| s.to_string()),
+    };
+    let cache = options.cache_file_path.clone().map(IconCache::load);
+    let cached = cache.as_ref().and_then(|cache| {
+        cache
+            .get(&key)
+            .or_else(|| cache.get(&CacheKey::from_class(class)))
+    });
+    match cached {
+        Some(entry) => {
+            print!("cache: ");
+            print_cache_entry(entry);
+        }
+        None => println!("cache: none"),
+    }
+
+    let desktop_index = DesktopIndex::build(&options.applications_dirs);
+    let min_score = get_min_fuzzy_score(class);
+    println!(
+        "candidates ({} desktop entries indexed, Exec is only matched for a running window):",
+        desktop_index.entries().len()
+    );
+    for (entry, kinds) in desktop_index.find_candidates(class, instance) {
+        println!(
+            "  {}  icon: {}  matched: {}  ({})",
+            entry.id,
+            entry.icon.as_deref().unwrap_or("-"),
+            kinds
+                .iter()
+                .map(|kind| match kind {
+                    MatchKind::Fuzzy(score) if *score < min_score => {
+                        format!("{} (below {})", kind, min_score)
+                    }
+                    kind => kind.to_string(),
+                })
+                .collect::<Vec<String>>()
+                .join(", "),
+            entry.path.display()
+        );
+    }
+    let chosen = desktop_index.find_by_window(class, instance, || None);
+    match chosen {
+        Some((entry, kind)) => println!("chosen: {} ({})", entry.id, kind),
+        None => println!("chosen: none"),
+    }
+
+    let icon = match (rule, cached) {
+        (Some(rule), _) => rule.icon.clone(),
+        (None, Some(entry)) => entry.icon.clone().unwrap_or_else(|| class.to_string()),
+        (None, None) => chosen
+            .and_then(|(entry, _)| entry.icon.clone())
+            .unwrap_or_else(|| class.to_string()),
+    };
+    println!("icon: {}", icon);
+    let mut icon_theme = IconTheme::from_settings(&options.settings.icon_theme);
+    match icon_theme.find_icon(&icon) {
+        Some(path) => println!(
+            "icon path: {} ({} theme, {}px @{}x)",
+            path.display(),
+            icon_theme.name,
+            icon_theme.size,
+            icon_theme.scale
+        ),
+        None => println!("icon path: none ({} theme)", icon_theme.name),
+    }
+}
+
+fn list_templates() {
+    for template in TEMPLATES.iter() {
+        println!("{:<10} {}", template.name, template.description);
+    }
+}
 
 fn main() {
     let options = get_options();
@@ -15,34 +320,21 @@ fn main() {
         }
     }
 
-    let mut con = I3::connect().expect("failed to connect");
-    let tree = get_tree(&mut con).expect("failed to send command");
-    let node = find_focused(&tree).expect("failed to find focused node");
-    if node.node_type == NodeType::Workspace && node.nodes.len() + node.floating_nodes.len() == 0 {
-        return;
-    }
-    let (prompt, styles) = get_prompt_and_styles(
-        &node,
-        &tree,
-        options.rofi_theme_file,
-        options.global_smart_gaps,
-        options.global_outer_gap,
-        options.cache_file_path,
-    );
-    if options.dump_styles {
-        if let Some(ref styles) = styles {
-            println!("{}", styles);
-        }
-    }
-    if prompt_user(prompt, options.rofi_config, styles) {
-        let outcomes = kill(&mut con).expect("failed to execute command");
-        for outcome in outcomes {
-            if !outcome.success {
-                eprintln!("command did not succeed");
-                if let Some(e) = outcome.error.as_ref() {
-                    eprintln!("{}", e);
-                }
+    match options.command {
+        Command::Kill => kill_focused(options),
+        Command::History => list_history(options),
+        Command::Reopen(index) => reopen(options, index),
+        Command::Undo => undo(options),
+        Command::Daemon => {
+            if let Err(error) = daemon::run(options) {
+                eprintln!("Daemon stopped: {}", error);
             }
         }
+        Command::Client => run_client(options),
+        Command::ExplainIcon(ref class, ref instance) => {
+            explain_icon(&options, class, instance.as_deref())
+        }
+        Command::Cache(ref command) => manage_cache(&options, command),
+        Command::ListTemplates => list_templates(),
     }
 }
@@ -0,0 +1,178 @@
+//! External color schemes, read on every prompt so they follow wallpaper changes.
+
+use crate::{settings::PaletteSettings, utils::fs::expand_home};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Colors by name, e.g. `color0`, `background`, `foreground`.
+pub type Palette = HashMap<String, String>;
+
+type PaletteReader = fn(&Path) -> io::Result<Palette>;
+
+#[derive(Debug, Deserialize)]
+struct PywalColors {
+    wallpaper: Option<String>,
+    #[serde(default)]
+    special: HashMap<String, String>,
+    #[serde(default)]
+    colors: HashMap<String, String>,
+}
+
+/// Reads the global resources (`*color0`, `*.background`...) of an X resources file.
+/// Resources of a given application are left out, `#define` macros are expanded.
+pub fn read_xresources(path: &Path) -> io::Result<Palette> {
+    let text = fs::read_to_string(path)?;
+    let mut defines: Vec<(String, String)> = Vec::new();
+    let mut palette = Palette::new();
+    for line in text.lines().map(str::trim) {
+        if let Some(define) = line.strip_prefix("#define") {
+            let mut words = define.split_whitespace();
+            if let (Some(name), Some(value)) = (words.next(), words.next()) {
+                defines.push((name.to_owned(), value.to_owned()));
+            }
+            continue;
+        }
+        if line.starts_with('!') || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+        let name = match key.strip_prefix('*') {
+            Some(name) => name.trim_start_matches('.'),
+            None => continue,
+        };
+        let value = defines
+            .iter()
+            .find(|(define, _)| define == value)
+            .map(|(_, value)| value.as_str())
+            .unwrap_or(value);
+        palette.insert(name.to_owned(), value.to_owned());
+    }
+    Ok(palette)
+}
+
+/// Reads a pywal `colors.json`, flattening its `special` and `colors` objects.
+pub fn read_pywal(path: &Path) -> io::Result<Palette> {
+    let text = fs::read_to_string(path)?;
+    let pywal: PywalColors = serde_json::from_str(&text)?;
+    let mut palette = Palette::new();
+    palette.extend(
+        pywal
+            .wallpaper
+            .map(|wallpaper| (String::from("wallpaper"), wallpaper)),
+    );
+    palette.extend(pywal.special);
+    palette.extend(pywal.colors);
+    Ok(palette)
+}
+
+/// Merges the configured sources, a missing or invalid one being skipped.
+pub fn read_palette(settings: &PaletteSettings) -> Palette {
+    let sources: [(&Option<PathBuf>, PaletteReader); 2] = [
+        (&settings.xresources, read_xresources),
+        (&settings.pywal, read_pywal),
+    ];
+    let mut palette = Palette::new();
+    for (path, read) in sources
+        .iter()
+        .filter_map(|(path, read)| path.as_ref().map(|path| (expand_home(path), read)))
+    {
+        match read(&path) {
+            Ok(colors) => palette.extend(colors),
+            Err(error) => eprintln!("Couldn't read palette {}: {}", path.display(), error),
+        }
+    }
+    palette
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    const XRESOURCES: &str = "\
+! comment
+#define base00 #1d1f21
+#define base05 #c5c8c6
+*.background: base00
+*foreground:  base05
+*.color0: #282a2e
+*color1:  #a54242
+URxvt*background: #000000
+URxvt.font: xft:Mono
+";
+
+    const PYWAL: &str = r##"{
+        "wallpaper": "/tmp/wall.png",
+        "special": { "background": "#0f1112", "foreground": "#c3c4c4" },
+        "colors": { "color0": "#0f1112", "color1": "#5a6a74" }
+    }"##;
+
+    /// Writes the contents to a file of the temporary directory, unique to the test.
+    fn write_temp(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("i3-window-killer-{}-{}", process::id(), name));
+        fs::write(&path, contents).expect("couldn't write the temporary file");
+        path
+    }
+
+    #[test]
+    fn xresources_expand_defines_and_skip_application_resources() {
+        let path = write_temp("Xresources", XRESOURCES);
+        let palette = read_xresources(&path).expect("couldn't read the resources");
+        fs::remove_file(&path).ok();
+        assert_eq!(palette["background"], "#1d1f21");
+        assert_eq!(palette["foreground"], "#c5c8c6");
+        assert_eq!(palette["color0"], "#282a2e");
+        assert_eq!(palette["color1"], "#a54242");
+        assert_eq!(palette.len(), 4);
+    }
+
+    #[test]
+    fn pywal_colors_are_flattened() {
+        let path = write_temp("colors.json", PYWAL);
+        let palette = read_pywal(&path).expect("couldn't read the colors");
+        fs::remove_file(&path).ok();
+        assert_eq!(palette["wallpaper"], "/tmp/wall.png");
+        assert_eq!(palette["foreground"], "#c3c4c4");
+        assert_eq!(palette["color1"], "#5a6a74");
+    }
+
+    #[test]
+    fn pywal_overrides_xresources() {
+        let settings = PaletteSettings {
+            xresources: Some(write_temp("merged-Xresources", XRESOURCES)),
+            pywal: Some(write_temp("merged-colors.json", PYWAL)),
+        };
+        let palette = read_palette(&settings);
+        settings
+            .xresources
+            .iter()
+            .chain(settings.pywal.iter())
+            .for_each(|path| {
+                fs::remove_file(path).ok();
+            });
+        assert_eq!(palette["background"], "#0f1112");
+        assert_eq!(palette["color0"], "#0f1112");
+        assert_eq!(palette["color1"], "#5a6a74");
+        assert_eq!(palette["wallpaper"], "/tmp/wall.png");
+    }
+
+    #[test]
+    fn missing_sources_are_skipped() {
+        let settings = PaletteSettings {
+            xresources: Some(write_temp("partial-Xresources", XRESOURCES)),
+            pywal: Some(env::temp_dir().join("i3-window-killer-missing-colors.json")),
+        };
+        let palette = read_palette(&settings);
+        settings.xresources.iter().for_each(|path| {
+            fs::remove_file(path).ok();
+        });
+        assert_eq!(palette["background"], "#1d1f21");
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{
+    cli::Options,
+    external_command::{get_config, get_output_scales, kill, prompt_user},
+    formatter::{get_nodes_info, get_prompt_and_styles, IconState},
+    history::{append_entries, collect_entries},
+    i3_config::I3Config,
+    layout::{has_windows, save_layout},
+    utils::{fs::create_parent_dir, i3_tree::find_focused},
+};
+use i3_ipc::{
+    reply::{Node, NodeType},
+    I3Stream,
+};
+use std::{io, sync::Mutex};
+
+/// Prompts for the focused node and kills it once confirmed, recording the killed
+/// windows to the history. Shared by the one-shot binary and the daemon, which
+/// detect sway once and only then query the output scales.
+pub fn kill_focused(
+    con: &mut I3Stream,
+    tree: &Node,
+    options: &Options,
+    icon_state: &Mutex<IconState>,
+    sway: bool,
+) -> io::Result<()> {
+    let node = match find_focused(tree) {
+        Some(node) => node,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "failed to find focused node",
+            ))
+        }
+    };
+    if node.node_type == NodeType::Workspace && node.nodes.len() + node.floating_nodes.len() == 0 {
+        return Ok(());
+    }
+    // the icon state is only locked while resolving, not during the prompt, so the
+    // daemon keeps watching desktop files meanwhile
+    let nodes_info = {
+        let mut icon_state = icon_state.lock().unwrap_or_else(|e| e.into_inner());
+        get_nodes_info(node, tree, &mut icon_state)
+    };
+    // i3 doesn't report output scales, only the settings set them
+    let output_scales = if sway {
+        get_output_scales().ok().flatten()
+    } else {
+        None
+    };
+    let (prompt, styles) = get_prompt_and_styles(
+        node,
+        tree,
+        &nodes_info,
+        options,
+        output_scales.as_ref(),
+        &get_config(con)
+            .map(|config| I3Config::parse(&config))
+            .unwrap_or_default(),
+    )?;
+    if options.dump_styles {
+        if let Some(ref styles) = styles {
+            println!("{}", styles);
+        }
+    }
+    if prompt_user(prompt, options.rofi_config.clone(), styles)? {
+        let layout = match options.layouts_dir {
+            Some(ref dir) if has_windows(node) => match save_layout(dir, node) {
+                Ok(path) => Some(path),
+                Err(error) => {
+                    eprintln!("Couldn't save layout: {}", error);
+                    None
+                }
+            },
+            _ => None,
+        };
+        let entries = collect_entries(node, tree, &nodes_info, layout);
+        let outcomes = kill(con)?;
+        for outcome in outcomes.iter() {
+            if !outcome.success {
+                eprintln!("command did not succeed");
+                if let Some(e) = outcome.error.as_ref() {
+                    eprintln!("{}", e);
+                }
+            }
+        }
+        if let Some(ref path) = options.history_file_path {
+            if outcomes.iter().any(|outcome| outcome.success) {
+                if let Err(error) =
+                    create_parent_dir(path).and_then(|_| append_entries(path, &entries))
+                {
+                    eprintln!("Couldn't write history: {}", error)
+                }
+            }
+        }
+    }
+    Ok(())
+}
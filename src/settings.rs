@@ -0,0 +1,353 @@
+//! User settings, read from `$XDG_CONFIG_HOME/i3-window-killer/config.toml`.
+
+use crate::utils::{escape, text};
+use regex::Regex;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub icons: IconRules,
+    pub icon_theme: IconThemeSettings,
+    pub template: TemplateSettings,
+    pub scale: ScaleSettings,
+    pub palette: PaletteSettings,
+    /// Template formatters, used as `{node.title | <name>}`.
+    pub formatters: HashMap<String, FormatterPreset>,
+}
+
+/// The `[template]` table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TemplateSettings {
+    /// Whether `nodes` holds the groups of windows sharing a class, rather than
+    /// every window.
+    pub group_by_class: bool,
+    /// Smallest container the prompt is readable in, in pixels.
+    pub min_prompt_width: i32,
+    pub min_prompt_height: i32,
+    pub placement: Placement,
+}
+
+/// Part of the focused container the prompt is placed over.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Placement {
+    /// Everything drawn for the container, title bar and borders included.
+    #[default]
+    Frame,
+    /// The window content, without its decorations.
+    Content,
+    /// The title bar (or tab) alone, the frame if there's none.
+    TitleBar,
+}
+
+impl Default for TemplateSettings {
+    fn default() -> Self {
+        TemplateSettings {
+            group_by_class: false,
+            min_prompt_width: 320,
+            min_prompt_height: 160,
+            placement: Placement::default(),
+        }
+    }
+}
+
+/// The `[scale]` table: scale factors of the outputs, overriding the ones sway reports.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawScaleSettings")]
+pub struct ScaleSettings {
+    /// Scale of the outputs missing from `outputs`, e.g. rofi's `dpi` divided by 96.
+    pub default: f64,
+    /// Scale per output name.
+    pub outputs: HashMap<String, f64>,
+}
+
+impl Default for ScaleSettings {
+    fn default() -> Self {
+        ScaleSettings {
+            default: 1.0,
+            outputs: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawScaleSettings {
+    default: f64,
+    outputs: HashMap<String, f64>,
+}
+
+impl Default for RawScaleSettings {
+    fn default() -> Self {
+        let settings = ScaleSettings::default();
+        RawScaleSettings {
+            default: settings.default,
+            outputs: settings.outputs,
+        }
+    }
+}
+
+impl TryFrom<RawScaleSettings> for ScaleSettings {
+    type Error = String;
+
+    fn try_from(raw: RawScaleSettings) -> Result<Self, Self::Error> {
+        fn is_valid(scale: f64) -> bool {
+            scale.is_finite() && scale > 0.0
+        }
+        if !is_valid(raw.default) {
+            return Err(format!("invalid default scale: {}", raw.default));
+        }
+        if let Some((output, scale)) = raw.outputs.iter().find(|(_, scale)| !is_valid(**scale)) {
+            return Err(format!("invalid scale of {}: {}", output, scale));
+        }
+        Ok(ScaleSettings {
+            default: raw.default,
+            outputs: raw.outputs,
+        })
+    }
+}
+
+/// The `[palette]` table: color schemes merged into the `palette` template variable,
+/// the pywal colors overriding the X resources.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaletteSettings {
+    /// e.g. `~/.Xresources`
+    pub xresources: Option<PathBuf>,
+    /// e.g. `~/.cache/wal/colors.json`
+    pub pywal: Option<PathBuf>,
+}
+
+/// The `[icon_theme]` table, used to resolve icon names to files.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IconThemeSettings {
+    /// Defaults to the GTK icon theme, then to `hicolor`.
+    pub name: Option<String>,
+    /// Preferred size, in pixels.
+    pub size: u32,
+    pub scale: u32,
+}
+
+impl Default for IconThemeSettings {
+    fn default() -> Self {
+        IconThemeSettings {
+            name: None,
+            size: 48,
+            scale: 1,
+        }
+    }
+}
+
+impl Settings {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        toml::from_str(&text).map_err(|error| error.to_string())
+    }
+}
+
+/// Forces the icon of the windows whose properties match every given pattern.
+#[derive(Debug, Clone)]
+pub struct IconRule {
+    pub class: Option<Regex>,
+    pub instance: Option<Regex>,
+    pub title: Option<Regex>,
+    /// Icon name or absolute path to an image.
+    pub icon: String,
+}
+
+impl IconRule {
+    pub fn is_match(&self, class: &str, instance: Option<&str>, title: &str) -> bool {
+        fn matches(pattern: &Option<Regex>, value: Option<&str>) -> bool {
+            match pattern {
+                Some(pattern) => value.is_some_and(|value| pattern.is_match(value)),
+                None => true,
+            }
+        }
+        matches(&self.class, Some(class))
+            && matches(&self.instance, instance)
+            && matches(&self.title, Some(title))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawIconRule {
+    class: Option<String>,
+    instance: Option<String>,
+    title: Option<String>,
+    icon: String,
+}
+
+/// The `[icons]` table: `"<class regex>" = "<icon>"` entries, and `[[icons.rules]]`
+/// matching on class, instance and title. Rules are tried before the class entries,
+/// each in file order.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "toml::value::Table")]
+pub struct IconRules {
+    rules: Vec<IconRule>,
+}
+
+impl TryFrom<toml::value::Table> for IconRules {
+    type Error = String;
+
+    fn try_from(table: toml::value::Table) -> Result<Self, Self::Error> {
+        fn parse_regex(pattern: &str) -> Result<Regex, String> {
+            Regex::new(pattern).map_err(|error| format!("invalid icon pattern: {}", error))
+        }
+        let mut rules: Vec<IconRule> = Vec::new();
+        let mut class_rules: Vec<IconRule> = Vec::new();
+        for (key, value) in table.into_iter() {
+            match value {
+                toml::Value::String(icon) => class_rules.push(IconRule {
+                    class: Some(parse_regex(&key)?),
+                    instance: None,
+                    title: None,
+                    icon,
+                }),
+                value if key == "rules" => {
+                    let raw_rules: Vec<RawIconRule> =
+                        value.try_into().map_err(|error| error.to_string())?;
+                    for raw_rule in raw_rules {
+                        let parse = |pattern: Option<String>| {
+                            pattern.as_deref().map(parse_regex).transpose()
+                        };
+                        rules.push(IconRule {
+                            class: parse(raw_rule.class)?,
+                            instance: parse(raw_rule.instance)?,
+                            title: parse(raw_rule.title)?,
+                            icon: raw_rule.icon,
+                        });
+                    }
+                }
+                _ => return Err(format!("icon of {} is not a string", key)),
+            }
+        }
+        rules.append(&mut class_rules);
+        Ok(IconRules { rules })
+    }
+}
+
+impl IconRules {
+    pub fn find(&self, class: &str, instance: Option<&str>, title: &str) -> Option<&IconRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.is_match(class, instance, title))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Case {
+    Upper,
+    Lower,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Escape {
+    #[default]
+    RasiString,
+    PangoMarkup,
+    Shell,
+    Raw,
+}
+
+impl Escape {
+    pub fn apply(self, value: &str) -> String {
+        match self {
+            Escape::RasiString => escape::rasi_string(value),
+            // their output still ends up in a rasi string
+            Escape::PangoMarkup => escape::rasi_string(&escape::pango_markup(value)),
+            Escape::Shell => escape::rasi_string(&escape::shell(value)),
+            Escape::Raw => value.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawReplacement {
+    pattern: String,
+    with: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawReplacement")]
+pub struct Replacement {
+    pub pattern: Regex,
+    pub with: String,
+}
+
+impl TryFrom<RawReplacement> for Replacement {
+    type Error = String;
+
+    fn try_from(raw: RawReplacement) -> Result<Self, Self::Error> {
+        Ok(Replacement {
+            pattern: Regex::new(&raw.pattern)
+                .map_err(|error| format!("invalid replacement pattern: {}", error))?,
+            with: raw.with,
+        })
+    }
+}
+
+fn default_ellipsis() -> String {
+    String::from("…")
+}
+
+/// A `[formatters.<name>]` table. Its steps are applied in the order of the fields.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FormatterPreset {
+    /// Regex replacements, `$1` standing for the first capture group.
+    #[serde(default)]
+    pub replace: Vec<Replacement>,
+    pub case: Option<Case>,
+    /// Maximum display width, the ellipsis included.
+    pub truncate: Option<usize>,
+    #[serde(default = "default_ellipsis")]
+    pub ellipsis: String,
+    /// Minimum display width, spaces being added on the left.
+    pub pad_left: Option<usize>,
+    /// Minimum display width, spaces being added on the right.
+    pub pad_right: Option<usize>,
+    #[serde(default)]
+    pub escape: Escape,
+}
+
+impl FormatterPreset {
+    pub fn apply(&self, value: &str) -> String {
+        let mut value = self
+            .replace
+            .iter()
+            .fold(value.to_owned(), |value, replacement| {
+                replacement
+                    .pattern
+                    .replace_all(&value, replacement.with.as_str())
+                    .into_owned()
+            });
+        value = match self.case {
+            Some(Case::Upper) => value.to_uppercase(),
+            Some(Case::Lower) => value.to_lowercase(),
+            None => value,
+        };
+        if let Some(max_width) = self.truncate {
+            value = text::truncate(&value, max_width, &self.ellipsis);
+        }
+        if let Some(width) = self.pad_left {
+            value = text::pad(&value, width, true);
+        }
+        if let Some(width) = self.pad_right {
+            value = text::pad(&value, width, false);
+        }
+        self.escape.apply(&value)
+    }
+}
@@ -0,0 +1,46 @@
+//! Templates compiled into the binary, selectable with `--template-name`.
+
+/// Styles every embedded template builds upon, the ones of the provided `config.rasi`,
+/// so they work without a rofi configuration file.
+const BASE_STYLES: &str = include_str!("../config.rasi");
+
+#[derive(Debug)]
+pub struct EmbeddedTemplate {
+    pub name: &'static str,
+    pub description: &'static str,
+    body: &'static str,
+}
+
+pub static TEMPLATES: [EmbeddedTemplate; 4] = [
+    EmbeddedTemplate {
+        name: "overlay",
+        description: "prompt over the killed container, listing its windows",
+        body: include_str!("../template.rasi"),
+    },
+    EmbeddedTemplate {
+        name: "centered",
+        description: "prompt centered on the screen, listing the windows",
+        body: include_str!("../templates/centered.rasi"),
+    },
+    EmbeddedTemplate {
+        name: "compact",
+        description: "prompt over the killed container, without windows",
+        body: include_str!("../templates/compact.rasi"),
+    },
+    EmbeddedTemplate {
+        name: "list",
+        description: "prompt centered on the screen, listing the windows grouped by class",
+        body: include_str!("../templates/list.rasi"),
+    },
+];
+
+impl EmbeddedTemplate {
+    /// The base styles, escaped for the template engine, followed by the template.
+    pub fn contents(&self) -> String {
+        format!("{}\n{}", BASE_STYLES.replace('{', "\\{"), self.body)
+    }
+}
+
+pub fn find_template(name: &str) -> Option<&'static EmbeddedTemplate> {
+    TEMPLATES.iter().find(|template| template.name == name)
+}
@@ -36,6 +36,88 @@ pub mod fs {
         }
     }
 
+    /// Replaces a leading `~` with the home directory.
+    pub fn expand_home(path: &Path) -> PathBuf {
+        match (path.strip_prefix("~"), env::var_os("HOME")) {
+            (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    pub fn get_default_state_dir() -> Option<PathBuf> {
+        match env::var_os("XDG_STATE_HOME") {
+            Some(p_os_str) => Some(PathBuf::from(p_os_str)),
+            None => env::var_os("HOME")
+                .map(|p_os_str| PathBuf::from(p_os_str).join(".local").join("state")),
+        }
+    }
+
+    pub fn get_default_config_dir() -> Option<PathBuf> {
+        match env::var_os("XDG_CONFIG_HOME") {
+            Some(p_os_str) => Some(PathBuf::from(p_os_str)),
+            None => env::var_os("HOME").map(|p_os_str| PathBuf::from(p_os_str).join(".config")),
+        }
+    }
+
+    /// Applications directories in order of precedence, following the XDG base
+    /// directory specification, then the Flatpak and Snap exports when they aren't
+    /// already part of `$XDG_DATA_DIRS`.
+    pub fn get_applications_dirs(extra_dirs: &[PathBuf]) -> Vec<PathBuf> {
+        let home = env::var_os("HOME").map(PathBuf::from);
+        let mut dirs: Vec<PathBuf> = extra_dirs.to_vec();
+        dirs.extend(get_data_home().map(|dir| dir.join("applications")));
+        dirs.extend(
+            home.iter()
+                .map(|home| home.join(".local/share/flatpak/exports/share/applications")),
+        );
+        dirs.extend(get_data_dirs().iter().map(|dir| dir.join("applications")));
+        dirs.push(PathBuf::from("/var/lib/flatpak/exports/share/applications"));
+        dirs.push(PathBuf::from("/var/lib/snapd/desktop/applications"));
+        keep_existing_dirs(dirs)
+    }
+
+    /// Icon base directories in order of precedence, as defined by the icon theme
+    /// specification.
+    pub fn get_icons_dirs() -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        dirs.extend(env::var_os("HOME").map(|home| PathBuf::from(home).join(".icons")));
+        dirs.extend(get_data_home().map(|dir| dir.join("icons")));
+        dirs.extend(get_data_dirs().iter().map(|dir| dir.join("icons")));
+        dirs.push(PathBuf::from("/usr/share/pixmaps"));
+        keep_existing_dirs(dirs)
+    }
+
+    fn get_data_home() -> Option<PathBuf> {
+        match env::var_os("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+            _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")),
+        }
+    }
+
+    fn get_data_dirs() -> Vec<PathBuf> {
+        match env::var("XDG_DATA_DIRS") {
+            Ok(dirs) if !dirs.is_empty() => env::split_paths(&dirs).collect(),
+            _ => vec![
+                PathBuf::from("/usr/local/share"),
+                PathBuf::from("/usr/share"),
+            ],
+        }
+    }
+
+    fn keep_existing_dirs(dirs: Vec<PathBuf>) -> Vec<PathBuf> {
+        let mut existing_dirs: Vec<PathBuf> = Vec::new();
+        for dir in dirs {
+            if dir.is_dir() && !existing_dirs.contains(&dir) {
+                existing_dirs.push(dir);
+            }
+        }
+        existing_dirs
+    }
+
+    pub fn get_default_runtime_dir() -> Option<PathBuf> {
+        env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from)
+    }
+
     pub fn get_default_icon_cache() -> Option<String> {
         match env::var_os("XDG_CACHE_HOME") {
             Some(p_os_str) => p_os_str.into_string().ok(),
@@ -57,7 +139,7 @@ pub mod fs {
 }
 
 pub mod i3_tree {
-    use i3_ipc::reply::Node;
+    use i3_ipc::reply::{Node, NodeType};
 
     pub fn get_child_iter<'a>(
         node: &'a Node,
@@ -65,6 +147,38 @@ pub mod i3_tree {
         node.nodes.iter().chain(node.floating_nodes.iter())
     }
 
+    pub fn get_node_chain<'a>(target: &Node, node: &'a Node) -> Option<Vec<&'a Node>> {
+        if node.id == target.id {
+            return Some(vec![node]);
+        }
+        get_child_iter(node).find_map(|n| {
+            get_node_chain(target, n).map(|chain| {
+                std::iter::once(node)
+                    .chain(chain)
+                    .collect::<Vec<&'a Node>>()
+            })
+        })
+    }
+
+    /// Returns every window of the node, in tree order.
+    pub fn get_windows(node: &Node) -> Vec<&Node> {
+        let mut windows: Vec<&Node> = Vec::new();
+        if node.window_properties.is_some() {
+            windows.push(node);
+        }
+        get_child_iter(node).for_each(|child| windows.append(&mut get_windows(child)));
+        windows
+    }
+
+    pub fn find_workspace<'a>(target: &Node, tree: &'a Node) -> Option<&'a Node> {
+        get_node_chain(target, tree).and_then(|chain| {
+            chain
+                .into_iter()
+                .rev()
+                .find(|n| n.node_type == NodeType::Workspace)
+        })
+    }
+
     pub fn find_focused(node: &Node) -> Option<&Node> {
         if node.focused {
             Some(node)
@@ -76,3 +190,159 @@ pub mod i3_tree {
         }
     }
 }
+
+pub mod proc {
+    use std::{fs, path::PathBuf};
+
+    pub fn read_cmdline(pid: u32) -> Vec<String> {
+        match fs::read(format!("/proc/{}/cmdline", pid)) {
+            Ok(bytes) => bytes
+                .split(|b| *b == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    pub fn read_cwd(pid: u32) -> Option<String> {
+        fs::read_link(format!("/proc/{}/cwd", pid))
+            .ok()
+            .and_then(|path| path.to_str().map(|s| s.to_owned()))
+    }
+
+    pub fn read_exe(pid: u32) -> Option<PathBuf> {
+        fs::read_link(format!("/proc/{}/exe", pid)).ok()
+    }
+}
+
+pub mod escape {
+    /// Escapes a value for a double-quoted rasi string, newlines becoming spaces.
+    pub fn rasi_string(value: &str) -> String {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '"' => escaped.push_str("\\\""),
+                '\n' | '\r' => escaped.push(' '),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    pub fn pango_markup(value: &str) -> String {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '\'' => escaped.push_str("&apos;"),
+                '"' => escaped.push_str("&quot;"),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    /// Quotes a value as a single shell word.
+    pub fn shell(value: &str) -> String {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn rasi_string_escapes_quotes_backslashes_and_newlines() {
+            assert_eq!(rasi_string(r#"He said "hi""#), r#"He said \"hi\""#);
+            assert_eq!(rasi_string(r"C:\Users\"), r"C:\\Users\\");
+            assert_eq!(rasi_string("two\nlines\r\n"), "two lines  ");
+        }
+
+        #[test]
+        fn pango_markup_escapes_entities() {
+            assert_eq!(
+                pango_markup(r#"<b>He said "hi" & 'bye'</b>"#),
+                "&lt;b&gt;He said &quot;hi&quot; &amp; &apos;bye&apos;&lt;/b&gt;"
+            );
+            assert_eq!(pango_markup(r"a\b"), r"a\b");
+        }
+
+        #[test]
+        fn shell_quotes_a_single_word() {
+            assert_eq!(shell("it's here"), r"'it'\''s here'");
+            assert_eq!(
+                shell(r#"He said "hi" \ $HOME"#),
+                r#"'He said "hi" \ $HOME'"#
+            );
+            assert_eq!(shell("two\nlines"), "'two\nlines'");
+        }
+
+        /// Built-in formatters chain their escaping with `rasi_string`.
+        #[test]
+        fn chained_escapes_stay_valid_rasi_strings() {
+            assert_eq!(
+                rasi_string(&pango_markup(r#"He said "hi""#)),
+                "He said &quot;hi&quot;"
+            );
+            assert_eq!(
+                rasi_string(&shell(r#"He said "hi""#)),
+                r#"'He said \"hi\"'"#
+            );
+            assert_eq!(rasi_string(&shell(r"a\b")), r"'a\\b'");
+        }
+    }
+}
+
+pub mod time {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Seconds since the Unix epoch, 0 for earlier times.
+    pub fn to_timestamp(time: SystemTime) -> u64 {
+        time.duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+
+    pub fn now() -> u64 {
+        to_timestamp(SystemTime::now())
+    }
+}
+
+pub mod text {
+    use unicode_width::UnicodeWidthStr;
+
+    /// Shortens a value to a display width (wide characters counting twice), the
+    /// ellipsis included.
+    pub fn truncate(value: &str, max_width: usize, ellipsis: &str) -> String {
+        if value.width() <= max_width {
+            return value.to_owned();
+        }
+        let max_width = max_width.saturating_sub(ellipsis.width());
+        let mut truncated = String::new();
+        let mut width = 0;
+        for c in value.chars() {
+            let char_width = unicode_width::UnicodeWidthChar::width(c).unwrap_or(0);
+            if width + char_width > max_width {
+                break;
+            }
+            width += char_width;
+            truncated.push(c);
+        }
+        truncated.push_str(ellipsis);
+        truncated
+    }
+
+    /// Pads a value with spaces up to a display width, on its left to align it right.
+    pub fn pad(value: &str, width: usize, left: bool) -> String {
+        let padding = " ".repeat(width.saturating_sub(value.width()));
+        if left {
+            padding + value
+        } else {
+            value.to_owned() + &padding
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use i3_ipc::reply::Node;
+use std::{fs, path::Path};
+
+pub fn read_tree(name: &str) -> Node {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(format!("{}.json", name));
+    let text = fs::read_to_string(&path).expect("couldn't read fixture");
+    serde_json::from_str(&text).expect("couldn't parse fixture")
+}
+
+pub fn find_node(node: &Node, id: usize) -> Option<&Node> {
+    if node.id == id {
+        return Some(node);
+    }
+    node.nodes
+        .iter()
+        .chain(node.floating_nodes.iter())
+        .find_map(|child| find_node(child, id))
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use common::{find_node, read_tree};
+use i3_ipc::reply::Node;
+use i3_window_killer::{
+    cli::SmartGapsOption,
+    formatter::{find_inherited_rect, NodeRect},
+    settings::Placement,
+};
+
+fn placed_rect(tree: &Node, id: usize, placement: Placement) -> NodeRect {
+    let target = find_node(tree, id).expect("no such node in fixture");
+    find_inherited_rect(target, tree, SmartGapsOption::Off, None, placement)
+}
+
+fn node_rect(left: i32, top: i32, right: i32, bottom: i32) -> NodeRect {
+    NodeRect {
+        top,
+        right,
+        bottom,
+        left,
+    }
+}
+
+#[test]
+fn tiled_window_is_clipped_by_workspace_gaps() {
+    let tree = read_tree("floating");
+    assert_eq!(
+        placed_rect(&tree, 33, Placement::Frame),
+        node_rect(20, 20, 1900, 1060)
+    );
+}
+
+#[test]
+fn focused_workspace_keeps_its_gaps() {
+    let tree = read_tree("floating");
+    assert_eq!(
+        placed_rect(&tree, 30, Placement::Frame),
+        node_rect(20, 20, 1900, 1060)
+    );
+}
+
+#[test]
+fn output_fullscreen_window_covers_its_output() {
+    let tree = read_tree("fullscreen_output");
+    assert_eq!(
+        placed_rect(&tree, 11, Placement::Frame),
+        node_rect(0, 0, 1920, 1080)
+    );
+}
+
+#[test]
+fn output_fullscreen_container_covers_its_output() {
+    let tree = read_tree("fullscreen_output");
+    assert_eq!(
+        placed_rect(&tree, 13, Placement::Frame),
+        node_rect(0, 0, 1920, 1080)
+    );
+}
+
+#[test]
+fn window_in_fullscreen_container_ignores_workspace_gaps() {
+    let tree = read_tree("fullscreen_output");
+    assert_eq!(
+        placed_rect(&tree, 15, Placement::Frame),
+        node_rect(960, 0, 1920, 1080)
+    );
+}
+
+#[test]
+fn global_fullscreen_window_covers_every_output() {
+    let tree = read_tree("fullscreen_global");
+    assert_eq!(
+        placed_rect(&tree, 22, Placement::Frame),
+        node_rect(0, 0, 3840, 1080)
+    );
+}
+
+#[test]
+fn floating_window_frame_is_not_clipped_by_its_workspace() {
+    let tree = read_tree("floating");
+    assert_eq!(
+        placed_rect(&tree, 32, Placement::Frame),
+        node_rect(1800, 100, 2200, 430)
+    );
+}
+
+#[test]
+fn floating_container_covers_its_frame() {
+    let tree = read_tree("floating");
+    assert_eq!(
+        placed_rect(&tree, 31, Placement::Frame),
+        node_rect(1800, 100, 2200, 430)
+    );
+}
+
+#[test]
+fn floating_container_content_covers_its_frame() {
+    let tree = read_tree("floating");
+    assert_eq!(
+        placed_rect(&tree, 31, Placement::Content),
+        node_rect(1800, 100, 2200, 430)
+    );
+}
+
+#[test]
+fn floating_container_title_bar_falls_back_to_its_frame() {
+    let tree = read_tree("floating");
+    assert_eq!(
+        placed_rect(&tree, 31, Placement::TitleBar),
+        node_rect(1800, 100, 2200, 430)
+    );
+}
+
+#[test]
+fn floating_window_content_excludes_decorations() {
+    let tree = read_tree("floating");
+    assert_eq!(
+        placed_rect(&tree, 32, Placement::Content),
+        node_rect(1802, 122, 2198, 428)
+    );
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::{find_node, read_tree};
+use i3_ipc::reply::Node;
+use i3_window_killer::{
+    cli::{Command, Options, SmartGapsOption},
+    formatter::{get_prompt_and_styles, AppInfo, NodeGeometry, NodeInfo},
+    i3_config::I3Config,
+    settings::Settings,
+    templates::TEMPLATES,
+};
+
+fn options(template_name: &str, settings: Settings) -> Options {
+    Options {
+        command: Command::Kill,
+        global_outer_gap: None,
+        global_smart_gaps: SmartGapsOption::Off,
+        rofi_config: None,
+        rofi_theme_file: None,
+        template_name: Some(template_name.to_owned()),
+        dump_styles: true,
+        no_cache: true,
+        cache_file_path: None,
+        history_file_path: None,
+        layouts_dir: None,
+        socket_path: None,
+        applications_dirs: Vec::new(),
+        settings,
+    }
+}
+
+/// The floating calculator of the fixture, as the icon lookup would describe it.
+fn calculator_info(node: &Node) -> NodeInfo {
+    NodeInfo {
+        id: node.id,
+        window: node.window,
+        class: String::from("Gnome-calculator"),
+        instance: String::from("gnome-calculator"),
+        title: String::from("Calculator \"2 + 2\" {x}"),
+        window_role: String::new(),
+        window_type: String::from("normal"),
+        icon: String::from("accessories-calculator"),
+        icon_path: String::new(),
+        app: AppInfo::default(),
+        marks: Vec::new(),
+        urgent: false,
+        focused: true,
+        floating: true,
+        fullscreen: false,
+        sticky: false,
+        scratchpad: false,
+        workspace: String::from("1"),
+        workspace_num: Some(1),
+        output: String::from("DP-1"),
+        rect: NodeGeometry::from(&node.rect),
+        deco_rect: NodeGeometry::from(&node.deco_rect),
+    }
+}
+
+fn render(template_name: &str, settings: Settings) -> String {
+    let tree = read_tree("floating");
+    let node = find_node(&tree, 32).expect("no such node in fixture");
+    let nodes_info = [calculator_info(node)];
+    let (prompt, styles) = get_prompt_and_styles(
+        node,
+        &tree,
+        &nodes_info,
+        &options(template_name, settings),
+        None,
+        &I3Config::default(),
+    )
+    .unwrap_or_else(|error| panic!("couldn't render {}: {}", template_name, error));
+    assert_eq!(prompt, "Close Gnome-calculator");
+    styles.unwrap_or_else(|| panic!("no styles for {}", template_name))
+}
+
+#[test]
+fn every_embedded_template_renders() {
+    for template in TEMPLATES.iter() {
+        for group_by_class in [false, true] {
+            let mut settings = Settings::default();
+            settings.template.group_by_class = group_by_class;
+            let styles = render(template.name, settings);
+            // the base styles keep their braces once rendered
+            assert!(
+                styles.contains("* {"),
+                "{} lost its base styles",
+                template.name
+            );
+            assert!(
+                !styles.contains("\\{"),
+                "{} kept escaped braces",
+                template.name
+            );
+        }
+    }
+}
+
+#[test]
+fn compact_template_covers_the_placement() {
+    let styles = render("compact", Settings::default());
+    assert!(styles.contains("margin: 100px calc(100% - 2200px) calc(100% - 430px) 1800px;"));
+}
+
+#[test]
+fn window_titles_are_escaped_for_rasi_strings() {
+    for template_name in ["overlay", "centered"] {
+        let styles = render(template_name, Settings::default());
+        assert!(
+            styles.contains(r#"str: "Calculator \"2 + 2\" {x}";"#),
+            "{} didn't escape the title",
+            template_name
+        );
+    }
+}